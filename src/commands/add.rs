@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::{collections::HashMap, env, fs, path::Path};
+
+use crate::{
+	commands::init::{
+		self,
+		conflicts::{self, Conflict},
+	},
+	hooks::Options,
+	interrupt,
+	recipes::{Kind, Recipe},
+};
+
+/// Where each variable is read from in the project's manifests, in order. The name falls back to
+/// the project's directory name.
+const METADATA_SOURCES: &[(&str, &str, &[&str])] = &[
+	("NAME", "Cargo.toml", &["package", "name"]),
+	("NAME", "pyproject.toml", &["project", "name"]),
+	("NAME", "pyproject.toml", &["tool", "poetry", "name"]),
+	("NAME", "package.json", &["name"]),
+	("DESCRIPTION", "Cargo.toml", &["package", "description"]),
+	("DESCRIPTION", "pyproject.toml", &["project", "description"]),
+	(
+		"DESCRIPTION",
+		"pyproject.toml",
+		&["tool", "poetry", "description"],
+	),
+	("DESCRIPTION", "package.json", &["description"]),
+	("AUTHOR", "Cargo.toml", &["package", "authors"]),
+	("AUTHOR", "pyproject.toml", &["project", "authors"]),
+	("AUTHOR", "pyproject.toml", &["tool", "poetry", "authors"]),
+	("AUTHOR", "package.json", &["author"]),
+	("VERSION", "Cargo.toml", &["package", "version"]),
+	("VERSION", "pyproject.toml", &["project", "version"]),
+	("VERSION", "pyproject.toml", &["tool", "poetry", "version"]),
+	("VERSION", "package.json", &["version"]),
+];
+
+#[derive(Debug, Parser)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct AddArgs {
+	/// Add-on recipe to apply to the current project
+	addon: String,
+
+	/// Fail if any placeholders are left without a value
+	#[clap(long)]
+	strict: bool,
+
+	/// Run the template's scripts, commands and plugins without asking whether to trust it
+	#[clap(long)]
+	trust: bool,
+
+	/// Never run the template's scripts, commands or plugins, without asking
+	#[clap(long, conflicts_with = "trust")]
+	no_trust: bool,
+
+	/// Show a spinner for each command instead of its output, which is kept in a log file
+	#[clap(long, short)]
+	quiet: bool,
+
+	/// How to handle files that already exist in the project
+	#[clap(long, value_enum, default_value_t)]
+	conflict: Conflict,
+}
+
+pub fn run(args: &AddArgs) -> Result<()> {
+	let recipe = Recipe::find(&args.addon)?;
+	if recipe.kind != Kind::Addon {
+		anyhow::bail!(
+			"Recipe {} creates new projects. Use `new {}` instead",
+			recipe.name,
+			recipe.name
+		);
+	}
+
+	let project_dir = env::current_dir().context("Failed to resolve current directory")?;
+	let mut metadata = project_metadata(&project_dir);
+	let name = match metadata.remove("NAME") {
+		Some(name) => name,
+		None => project_dir
+			.file_name()
+			.and_then(|name| name.to_str())
+			.map(str::to_string)
+			.context("Invalid project directory")?,
+	};
+	let recipe = with_defaults(recipe, metadata);
+	let options = Options {
+		strict: args.strict,
+		trust_template: init::trust(args.trust, args.no_trust),
+		quiet: args.quiet,
+		..Options::default()
+	};
+
+	interrupt::install()?;
+
+	apply(&recipe, &project_dir, &name, &options, args.conflict)
+}
+
+/// Renders the add-on next to `project_dir` and merges it in once every conflict is resolved, so a
+/// failure before then leaves the project as it was. The add-on's commands then run in the project.
+pub fn apply(
+	recipe: &Recipe,
+	project_dir: &Path,
+	name: &str,
+	options: &Options,
+	conflict: Conflict,
+) -> Result<()> {
+	let parent = project_dir
+		.parent()
+		.context("Project directory has no parent directory")?;
+	let staging = tempfile::Builder::new()
+		.prefix(&format!(".{name}.new-staging"))
+		.tempdir_in(parent)
+		.context("Failed to create staging directory")?;
+	let staged = staging.path().join(name);
+
+	recipe.apply(&staged, project_dir, name, options, || {
+		conflicts::move_into(&staged, project_dir, conflict)
+	})
+}
+
+/// The project's name, description, author and version, from the first manifest that has each.
+fn project_metadata(project_dir: &Path) -> HashMap<String, String> {
+	let mut manifests = HashMap::new();
+	let mut metadata = HashMap::new();
+
+	for (variable, file, keys) in METADATA_SOURCES {
+		if metadata.contains_key(*variable) {
+			continue;
+		}
+
+		let manifest = manifests
+			.entry(*file)
+			.or_insert_with(|| read_manifest(&project_dir.join(file)));
+		if let Some(value) = manifest
+			.as_ref()
+			.and_then(|manifest| keys.iter().try_fold(manifest, |value, key| value.get(key)))
+			.and_then(metadata_value)
+		{
+			metadata.insert((*variable).to_string(), value);
+		}
+	}
+
+	// Scoped npm packages are named `@scope/name`.
+	if let Some(name) = metadata.get_mut("NAME") {
+		*name = name.rsplit('/').next().unwrap_or(name).to_string();
+	}
+
+	metadata
+}
+
+/// A manifest value as text. Authors can be a list, a `{ name = ... }` table, or `Name <email>`,
+/// and only the first author's name is kept.
+fn metadata_value(value: &serde_json::Value) -> Option<String> {
+	match value {
+		serde_json::Value::String(text) => {
+			let text = text
+				.split_once(" <")
+				.map_or(text.as_str(), |(name, _)| name);
+			Some(text.trim().to_string()).filter(|text| !text.is_empty())
+		},
+		serde_json::Value::Array(values) => values.first().and_then(metadata_value),
+		serde_json::Value::Object(fields) => fields.get("name").and_then(metadata_value),
+		_ => None,
+	}
+}
+
+/// Adds `metadata` to the recipe's replacements, where the recipe doesn't set them itself.
+fn with_defaults(mut recipe: Recipe, metadata: HashMap<String, String>) -> Recipe {
+	let replacements = recipe
+		.extra
+		.entry("replacements")
+		.or_insert_with(|| toml::Value::Table(toml::Table::new()));
+	if let toml::Value::Table(replacements) = replacements {
+		for (key, value) in metadata {
+			replacements
+				.entry(key)
+				.or_insert(toml::Value::String(value));
+		}
+	}
+
+	recipe
+}
+
+/// Reads a TOML or JSON manifest, skipping it if it's missing or invalid.
+fn read_manifest(path: &Path) -> Option<serde_json::Value> {
+	let contents = fs::read_to_string(path).ok()?;
+
+	if path
+		.extension()
+		.is_some_and(|extension| extension == "json")
+	{
+		serde_json::from_str(&contents).ok()
+	} else {
+		serde_json::to_value(contents.parse::<toml::Table>().ok()?).ok()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::project_metadata;
+	use std::{collections::HashMap, fs};
+
+	#[test]
+	fn project_metadata_reads_manifests() -> anyhow::Result<()> {
+		let project = tempfile::Builder::new().prefix("new-cli-add").tempdir()?;
+		assert_eq!(project_metadata(project.path()), HashMap::new());
+
+		fs::write(
+			project.path().join("package.json"),
+			r#"{ "name": "@acme/web-app", "description": "A web app", "author": { "name": "Ada" } }"#,
+		)?;
+		fs::write(
+			project.path().join("pyproject.toml"),
+			"[tool.poetry]\nname = \"py-app\"\nauthors = [\"Grace Hopper <grace@example.com>\"]\n",
+		)?;
+
+		assert_eq!(
+			project_metadata(project.path()),
+			HashMap::from([
+				("NAME".to_string(), "py-app".to_string()),
+				("DESCRIPTION".to_string(), "A web app".to_string()),
+				("AUTHOR".to_string(), "Grace Hopper".to_string()),
+			])
+		);
+
+		Ok(())
+	}
+}
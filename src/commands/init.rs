@@ -3,13 +3,24 @@ use clap::Parser;
 use inquire::Text;
 use path_absolutize::Absolutize;
 use std::{
-	fs,
+	cell::Cell,
+	env, fs,
 	path::{Path, PathBuf},
 };
 
-use crate::recipes::Recipe;
+use crate::{
+	hooks::Options,
+	interrupt,
+	recipes::{Kind, Recipe},
+};
+
+pub mod conflicts;
+mod preview;
+
+pub use conflicts::Conflict;
 
 #[derive(Debug, Parser)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct InitArgs {
 	/// Template recipe to use for the new project
 	#[clap(required = true)]
@@ -17,6 +28,46 @@ pub struct InitArgs {
 
 	/// Directory where to create the new project
 	directory: Option<PathBuf>,
+
+	/// Create the project in the current directory
+	#[clap(long, conflicts_with = "directory")]
+	here: bool,
+
+	/// Generate into a directory that isn't empty
+	#[clap(long)]
+	force: bool,
+
+	/// How to handle generated files that already exist, with --force
+	#[clap(long, value_enum, default_value_t, requires = "force")]
+	conflict: Conflict,
+
+	/// Fail if any placeholders are left without a value
+	#[clap(long)]
+	strict: bool,
+
+	/// Run the template's scripts, commands and plugins without asking whether to trust it
+	#[clap(long)]
+	trust: bool,
+
+	/// Never run the template's scripts, commands or plugins, without asking
+	#[clap(long, conflicts_with = "trust")]
+	no_trust: bool,
+
+	/// Show a spinner for each command instead of its output, which is kept in a log file
+	#[clap(long, short)]
+	quiet: bool,
+
+	/// Preview the generated project in a temporary directory, without running any commands
+	#[clap(long)]
+	dry_run: bool,
+
+	/// Keep the temporary directory of a dry run to inspect it
+	#[clap(long, requires = "dry_run")]
+	keep_temp: bool,
+
+	/// Keep the partially generated project if generation fails
+	#[clap(long, conflicts_with = "dry_run")]
+	keep_on_failure: bool,
 }
 
 pub fn run(args: &InitArgs) -> Result<()> {
@@ -26,12 +77,176 @@ pub fn run(args: &InitArgs) -> Result<()> {
 			.context("Missing template recipe. Use `new list` to see available templates")?,
 	)?;
 
-	let directory = resolve_directory(args.directory.clone())?;
-	ensure_directory_available(&directory)?;
+	if recipe.kind == Kind::Addon {
+		anyhow::bail!(
+			"Recipe {} is an add-on. Use `new add {}` in an existing project instead",
+			recipe.name,
+			recipe.name
+		);
+	}
 
+	let directory = if args.here {
+		env::current_dir().context("Failed to resolve current directory")?
+	} else {
+		resolve_directory(args.directory.clone())?
+	};
 	let name = project_name(&directory)?;
+	let options = Options {
+		strict: args.strict,
+		dry_run: args.dry_run,
+		trust_template: trust(args.trust, args.no_trust),
+		quiet: args.quiet,
+	};
 
-	recipe.run(&directory, &name)
+	if args.dry_run {
+		return dry_run(&recipe, &name, &options, args.keep_temp);
+	}
+
+	ensure_directory_available(&directory, args.force)?;
+	interrupt::install()?;
+
+	generate(
+		&recipe,
+		&directory,
+		&name,
+		&options,
+		args.conflict,
+		args.keep_on_failure,
+	)
+}
+
+/// Renders the project in a staging directory next to `directory` and moves it into place, where
+/// the commands then run. A failure before the move removes the staging directory, and one after
+/// it removes the project again, unless it was merged into a directory that wasn't empty.
+pub fn generate(
+	recipe: &Recipe,
+	directory: &Path,
+	name: &str,
+	options: &Options,
+	conflict: Conflict,
+	keep_on_failure: bool,
+) -> Result<()> {
+	let parent = directory
+		.parent()
+		.context("Project directory has no parent directory")?;
+	let staging = tempfile::Builder::new()
+		.prefix(&format!(".{name}.new-staging"))
+		.tempdir_in(parent)
+		.context("Failed to create staging directory")?;
+	let staged = staging.path().join(name);
+
+	let existed = directory.is_dir();
+	let fresh = !existed || is_empty(directory)?;
+	let moved = Cell::new(false);
+
+	let result = recipe
+		.apply(&staged, directory, name, options, || {
+			interrupt::check()?;
+			move_into_place(&staged, directory, conflict)?;
+			moved.set(true);
+			Ok(())
+		})
+		.and_then(|()| interrupt::check());
+
+	match result {
+		Ok(()) => Ok(()),
+		Err(err) if keep_on_failure => {
+			let kept = if moved.get() {
+				directory.to_path_buf()
+			} else {
+				staging.keep().join(name)
+			};
+			println!(
+				"🔍 Kept the partially generated project at {}",
+				kept.display()
+			);
+			Err(err)
+		},
+		Err(err) if moved.get() && fresh => {
+			if let Err(cleanup) = remove_project(directory, existed) {
+				eprintln!("{cleanup:#}");
+			}
+			Err(err)
+		},
+		Err(err) => Err(err),
+	}
+}
+
+/// Removes a project that failed after being moved into `directory`, keeping the directory itself
+/// when it was already there.
+fn remove_project(directory: &Path, existed: bool) -> Result<()> {
+	if !existed {
+		return fs::remove_dir_all(directory)
+			.with_context(|| format!("Failed to remove project {}", directory.display()));
+	}
+
+	for entry in fs::read_dir(directory)
+		.with_context(|| format!("Failed to read project directory {}", directory.display()))?
+	{
+		let path = entry?.path();
+		if path.is_dir() && !path.is_symlink() {
+			fs::remove_dir_all(&path)
+		} else {
+			fs::remove_file(&path)
+		}
+		.with_context(|| format!("Failed to remove {}", path.display()))?;
+	}
+
+	Ok(())
+}
+
+fn move_into_place(staged: &Path, directory: &Path, conflict: Conflict) -> Result<()> {
+	if directory.is_dir() {
+		if !is_empty(directory)? {
+			return conflicts::move_into(staged, directory, conflict);
+		}
+
+		fs::remove_dir(directory).with_context(|| {
+			format!(
+				"Failed to replace project directory {}",
+				directory.display()
+			)
+		})?;
+	}
+
+	fs::rename(staged, directory).with_context(|| {
+		format!(
+			"Failed to move project into place at {}",
+			directory.display()
+		)
+	})
+}
+
+/// Generates the project in a temporary directory and prints what it would look like.
+fn dry_run(recipe: &Recipe, name: &str, options: &Options, keep_temp: bool) -> Result<()> {
+	let temp_dir = tempfile::Builder::new()
+		.prefix("new-dry-run")
+		.tempdir()
+		.context("Failed to create temporary directory")?;
+	let directory = temp_dir.path().join(name);
+
+	recipe.run(&directory, name, options)?;
+	preview::print(&directory)?;
+
+	let git_dir = directory.join(".git");
+	if git_dir.is_dir() {
+		fs::remove_dir_all(&git_dir).context("Failed to remove .git directory from preview")?;
+	}
+
+	if keep_temp {
+		println!(
+			"📁 Kept the preview at {}",
+			temp_dir.keep().join(name).display()
+		);
+	}
+
+	Ok(())
+}
+
+/// Whether `--trust` or `--no-trust` decided to trust the template, leaving it to a prompt
+/// otherwise.
+pub const fn trust(trust: bool, no_trust: bool) -> Option<bool> {
+	if trust || no_trust { Some(trust) } else { None }
 }
 
 fn resolve_directory(directory: Option<PathBuf>) -> Result<PathBuf> {
@@ -58,15 +273,12 @@ fn project_name(directory: &Path) -> Result<String> {
 		.context("Invalid project directory")
 }
 
-fn ensure_directory_available(directory: &Path) -> Result<()> {
+fn ensure_directory_available(directory: &Path, force: bool) -> Result<()> {
 	if directory.is_dir() {
-		if directory
-			.read_dir()
-			.with_context(|| format!("Failed to read project directory {}", directory.display()))?
-			.next()
-			.is_some()
-		{
-			anyhow::bail!("Project directory already exists and is not empty");
+		if !force && !is_empty(directory)? {
+			anyhow::bail!(
+				"Project directory already exists and is not empty. Use --force to generate into it anyway"
+			);
 		}
 
 		return Ok(());
@@ -83,3 +295,11 @@ fn ensure_directory_available(directory: &Path) -> Result<()> {
 
 	Ok(())
 }
+
+fn is_empty(directory: &Path) -> Result<bool> {
+	Ok(directory
+		.read_dir()
+		.with_context(|| format!("Failed to read project directory {}", directory.display()))?
+		.next()
+		.is_none())
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use inquire::Select;
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::git;
+
+/// What to do with generated files that already exist in the project directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Conflict {
+	/// Keep the existing file
+	Skip,
+	/// Replace the existing file with the generated one
+	Overwrite,
+	/// Show a diff and ask for every file
+	#[default]
+	Prompt,
+	/// Keep both versions, wrapping the differences in conflict markers
+	Merge,
+}
+
+/// How a conflicting file ended up, once any prompt has been answered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Resolution {
+	Kept,
+	Overwritten,
+	Merged,
+}
+
+/// A change to the project directory, planned before anything is moved.
+enum Step {
+	/// Moves a generated entry that doesn't exist in the project yet.
+	Move,
+	/// Replaces the existing entry with the generated one.
+	Overwrite,
+	/// Writes both versions, wrapped in conflict markers, over the existing file.
+	Merge(Vec<u8>),
+}
+
+struct Planned {
+	source: PathBuf,
+	target: PathBuf,
+	relative: PathBuf,
+	step: Step,
+}
+
+/// Moves the generated project at `staged` into the existing `directory`, resolving every file
+/// that exists in both with `strategy`. Every conflict is resolved before anything is moved, so a
+/// declined prompt leaves the project as it was.
+pub fn move_into(staged: &Path, directory: &Path, strategy: Conflict) -> Result<()> {
+	let mut steps = Vec::new();
+	let mut resolved = Vec::new();
+	plan(
+		staged,
+		directory,
+		Path::new(""),
+		strategy,
+		&mut steps,
+		&mut resolved,
+	)?;
+
+	for Planned {
+		source,
+		target,
+		relative,
+		step,
+	} in steps
+	{
+		match step {
+			Step::Move => fs::rename(&source, &target)
+				.with_context(|| format!("Failed to move {} into place", relative.display()))?,
+			Step::Overwrite => if target.is_dir() {
+				fs::remove_dir_all(&target)
+			} else {
+				fs::remove_file(&target)
+			}
+			.and_then(|()| fs::rename(&source, &target))
+			.with_context(|| format!("Failed to overwrite {}", relative.display()))?,
+			Step::Merge(merged) => fs::write(&target, merged)
+				.with_context(|| format!("Failed to merge {}", relative.display()))?,
+		}
+	}
+
+	if !resolved.is_empty() {
+		println!("🔀 Resolved {} conflicting files", resolved.len());
+		for (path, resolution) in resolved {
+			let resolution = match resolution {
+				Resolution::Kept => "kept existing",
+				Resolution::Overwritten => "overwritten",
+				Resolution::Merged => "merged, check for conflict markers",
+			};
+			println!("  - {} ({resolution})", path.display());
+		}
+	}
+
+	Ok(())
+}
+
+fn plan(
+	staged: &Path,
+	directory: &Path,
+	relative: &Path,
+	strategy: Conflict,
+	steps: &mut Vec<Planned>,
+	resolved: &mut Vec<(PathBuf, Resolution)>,
+) -> Result<()> {
+	let entries = fs::read_dir(staged)
+		.and_then(Iterator::collect::<Result<Vec<_>, _>>)
+		.with_context(|| format!("Failed to read directory {}", staged.display()))?;
+
+	for entry in entries {
+		let source = entry.path();
+		let target = directory.join(entry.file_name());
+		let relative = relative.join(entry.file_name());
+
+		if target.symlink_metadata().is_err() {
+			steps.push(Planned {
+				source,
+				target,
+				relative,
+				step: Step::Move,
+			});
+			continue;
+		}
+
+		if source.is_dir() && target.is_dir() {
+			// An existing repository is never merged with one created by the recipe's commands.
+			if entry.file_name() != ".git" {
+				plan(&source, &target, &relative, strategy, steps, resolved)?;
+			}
+			continue;
+		}
+
+		let generated = fs::read(&source).ok();
+		let existing = fs::read(&target).ok();
+		if generated.is_some() && generated == existing {
+			continue;
+		}
+
+		let strategy = match strategy {
+			Conflict::Prompt => prompt(&relative, existing.as_deref(), generated.as_deref())?,
+			strategy => strategy,
+		};
+
+		let (step, resolution) = match (strategy, existing, generated) {
+			(Conflict::Overwrite, ..) => (Step::Overwrite, Resolution::Overwritten),
+			(Conflict::Merge, Some(existing), Some(generated))
+				if !existing.contains(&0) && !generated.contains(&0) =>
+			{
+				(
+					Step::Merge(git::merge_with_markers(&existing, &generated)?),
+					Resolution::Merged,
+				)
+			},
+			// Directories, binary files, and files of one kind over the other can't be merged.
+			_ => {
+				resolved.push((relative, Resolution::Kept));
+				continue;
+			},
+		};
+
+		resolved.push((relative.clone(), resolution));
+		steps.push(Planned {
+			source,
+			target,
+			relative,
+			step,
+		});
+	}
+
+	Ok(())
+}
+
+fn prompt(relative: &Path, existing: Option<&[u8]>, generated: Option<&[u8]>) -> Result<Conflict> {
+	match (existing, generated) {
+		(Some(existing), Some(generated)) => {
+			println!("{}", git::diff(existing, generated, relative)?);
+		},
+		_ => println!(
+			"{} is a directory on one side and a file on the other",
+			relative.display()
+		),
+	}
+
+	let choices = [
+		("Keep the existing file", Conflict::Skip),
+		("Overwrite it with the generated file", Conflict::Overwrite),
+		("Merge both, with conflict markers", Conflict::Merge),
+	];
+	let choice = Select::new(
+		&format!(
+			"{} already exists. What should happen to it?",
+			relative.display()
+		),
+		choices.iter().map(|(label, _)| *label).collect(),
+	)
+	.prompt()
+	.with_context(|| format!("Failed to prompt for {}", relative.display()))?;
+
+	Ok(choices
+		.into_iter()
+		.find(|(label, _)| *label == choice)
+		.map_or(Conflict::Skip, |(_, conflict)| conflict))
+}
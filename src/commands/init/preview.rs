@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::git::{self, Change};
+
+/// Prints the project's file tree, marking the files that changed compared to the template.
+pub fn print(project_dir: &Path) -> Result<()> {
+	let changes = git::changes(project_dir)?;
+
+	println!("🌳 {}", display_name(project_dir));
+	print_dir(project_dir, Path::new(""), "", &changes)?;
+
+	let deleted = changes
+		.iter()
+		.filter(|(_, change)| **change == Change::Deleted)
+		.map(|(path, _)| path)
+		.collect::<Vec<_>>();
+	if !deleted.is_empty() {
+		println!("🗑️  Removed from the template:");
+		for path in deleted {
+			println!("  - {}", path.display());
+		}
+	}
+
+	Ok(())
+}
+
+fn print_dir(
+	dir: &Path,
+	relative: &Path,
+	indent: &str,
+	changes: &BTreeMap<PathBuf, Change>,
+) -> Result<()> {
+	let mut entries = fs::read_dir(dir)
+		.and_then(Iterator::collect::<Result<Vec<_>, _>>)
+		.with_context(|| format!("Failed to read directory {}", dir.display()))?;
+	entries.retain(|entry| entry.file_name() != ".git");
+	entries.sort_by_key(fs::DirEntry::file_name);
+
+	let count = entries.len();
+	for (index, entry) in entries.into_iter().enumerate() {
+		let is_last = index + 1 == count;
+		let path = relative.join(entry.file_name());
+		let is_dir = entry.file_type().is_ok_and(|ft| ft.is_dir());
+
+		let marker = match changes.get(&path) {
+			Some(Change::Added) => " (new)",
+			Some(Change::Modified) => " (changed)",
+			_ => "",
+		};
+
+		println!(
+			"{indent}{}{}{}",
+			if is_last { "└── " } else { "├── " },
+			entry.file_name().to_string_lossy(),
+			marker
+		);
+
+		if is_dir {
+			let indent = format!("{indent}{}", if is_last { "    " } else { "│   " });
+			print_dir(&entry.path(), &path, &indent, changes)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn display_name(path: &Path) -> String {
+	path.file_name().map_or_else(
+		|| path.display().to_string(),
+		|name| name.to_string_lossy().to_string(),
+	)
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use std::path::Path;
 
-use crate::recipes::{self, ReadError, Recipe, recipes_dir};
+use crate::recipes::{self, Kind, ReadError, Recipe, recipes_dir};
 
 pub fn run() -> Result<()> {
 	let mut recipes: Vec<_> = recipes::list()?.into_iter().collect();
@@ -18,6 +18,7 @@ pub fn run() -> Result<()> {
 
 	for (path, recipe) in recipes {
 		match recipe {
+			Ok(recipe) if recipe.kind == Kind::Addon => println!("✅ {} (add-on)", recipe.name),
 			Ok(recipe) => println!("✅ {}", recipe.name),
 			Err(ReadError::Reading(error)) => {
 				println!("❌ {} (unreadable)", name_from_path(&path));
@@ -1,5 +1,6 @@
 use clap::Subcommand;
 
+pub mod add;
 pub mod edit;
 pub mod init;
 pub mod list;
@@ -18,4 +19,7 @@ pub enum Commands {
 
 	/// Create a new project from a template
 	Init(init::InitArgs),
+
+	/// Apply an add-on recipe to the project in the current directory
+	Add(add::AddArgs),
 }
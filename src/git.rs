@@ -1,8 +1,15 @@
 use anyhow::{Context, Result};
-use git2::{FetchOptions, build::RepoBuilder};
-use std::path::Path;
+use git2::{
+	AttrCheckFlags, AttrValue, Config, DiffOptions, FetchOptions, Patch, Repository, Status,
+	StatusOptions, build::RepoBuilder,
+};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	path::{Path, PathBuf},
+};
 
-pub fn clone_repo(repo: &str, branch: Option<&str>, destination: &Path) -> Result<()> {
+/// Clones `repo` into `destination`, returning the SHA of the commit it checked out.
+pub fn clone_repo(repo: &str, branch: Option<&str>, destination: &Path) -> Result<String> {
 	let repo_url = normalize_repo(repo)?;
 
 	let mut builder = RepoBuilder::new();
@@ -16,11 +23,18 @@ pub fn clone_repo(repo: &str, branch: Option<&str>, destination: &Path) -> Resul
 		builder.branch(branch);
 	}
 
-	builder
+	let repo = builder
 		.clone(&repo_url, destination)
 		.with_context(|| format!("Failed to clone template repository {repo_url}"))?;
 
-	Ok(())
+	let sha = repo
+		.head()
+		.and_then(|head| head.peel_to_commit())
+		.with_context(|| format!("Failed to read the cloned commit of {repo_url}"))?
+		.id()
+		.to_string();
+
+	Ok(sha)
 }
 
 fn normalize_repo(repo: &str) -> Result<String> {
@@ -57,3 +71,202 @@ fn normalize_repo(repo: &str) -> Result<String> {
 fn is_local_repo(repo: &str) -> bool {
 	repo.starts_with("file://") || Path::new(repo).exists()
 }
+
+/// Tracked paths marked `export-ignore` in the repository's `.gitattributes`, like `git archive`
+/// would leave out. Directories are returned instead of the files inside them.
+pub fn export_ignored(repo_dir: &Path) -> Result<BTreeSet<PathBuf>> {
+	let repo = open_repo(repo_dir)?;
+	let mut checked = HashMap::new();
+	let mut ignored = BTreeSet::new();
+
+	for path in tracked_paths(&repo)? {
+		// Walk from the top-level directory down, so the outermost ignored path wins.
+		for ancestor in path
+			.ancestors()
+			.collect::<Vec<_>>()
+			.into_iter()
+			.rev()
+			.skip(1)
+		{
+			let is_ignored = if let Some(is_ignored) = checked.get(ancestor) {
+				*is_ignored
+			} else {
+				let is_ignored = is_set(&repo, ancestor, "export-ignore")?;
+				checked.insert(ancestor.to_path_buf(), is_ignored);
+				is_ignored
+			};
+
+			if is_ignored {
+				ignored.insert(ancestor.to_path_buf());
+				break;
+			}
+		}
+	}
+
+	Ok(ignored)
+}
+
+/// Tracked paths marked `binary` or `-text` in the repository's `.gitattributes`.
+pub fn binary_paths(repo_dir: &Path) -> Result<HashSet<PathBuf>> {
+	let repo = open_repo(repo_dir)?;
+
+	tracked_paths(&repo)?
+		.into_iter()
+		.filter_map(|path| {
+			let binary = is_set(&repo, &path, "binary").and_then(|binary| {
+				Ok(binary || attr_value(&repo, &path, "text")? == AttrValue::False)
+			});
+
+			binary.map(|binary| binary.then_some(path)).transpose()
+		})
+		.collect()
+}
+
+/// The user's `user.name` and `user.email` from their git configuration, when set.
+pub struct Identity {
+	pub name: Option<String>,
+	pub email: Option<String>,
+}
+
+pub fn identity() -> Result<Identity> {
+	let config = Config::open_default().context("Failed to read git configuration")?;
+
+	Ok(Identity {
+		name: config.get_string("user.name").ok(),
+		email: config.get_string("user.email").ok(),
+	})
+}
+
+fn open_repo(repo_dir: &Path) -> Result<Repository> {
+	Repository::open(repo_dir)
+		.with_context(|| format!("Failed to open template repository {}", repo_dir.display()))
+}
+
+fn tracked_paths(repo: &Repository) -> Result<Vec<PathBuf>> {
+	let index = repo
+		.index()
+		.context("Failed to read template repository index")?;
+
+	Ok(index
+		.iter()
+		.map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+		.collect())
+}
+
+fn is_set(repo: &Repository, path: &Path, name: &str) -> Result<bool> {
+	Ok(attr_value(repo, path, name)? == AttrValue::True)
+}
+
+fn attr_value<'a>(repo: &'a Repository, path: &Path, name: &str) -> Result<AttrValue<'a>> {
+	let value = repo
+		.get_attr_bytes(path, name, AttrCheckFlags::FILE_THEN_INDEX)
+		.with_context(|| format!("Failed to read `{name}` attribute of {}", path.display()))?;
+
+	Ok(AttrValue::from_bytes(value))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+	Added,
+	Modified,
+	Deleted,
+}
+
+/// How the working tree of the repository at `repo_dir` differs from the commit it was cloned at.
+pub fn changes(repo_dir: &Path) -> Result<BTreeMap<PathBuf, Change>> {
+	let repo = open_repo(repo_dir)?;
+	let mut options = StatusOptions::new();
+	options
+		.include_untracked(true)
+		.recurse_untracked_dirs(true)
+		.include_ignored(true)
+		.recurse_ignored_dirs(true);
+
+	let statuses = repo
+		.statuses(Some(&mut options))
+		.context("Failed to compare the project with the template")?;
+
+	Ok(statuses
+		.iter()
+		.filter_map(|entry| {
+			let status = entry.status();
+			let change = if status.intersects(Status::WT_NEW | Status::IGNORED) {
+				Change::Added
+			} else if status.contains(Status::WT_DELETED) {
+				Change::Deleted
+			} else if status.intersects(Status::WT_MODIFIED | Status::WT_TYPECHANGE) {
+				Change::Modified
+			} else {
+				return None;
+			};
+
+			Some((
+				PathBuf::from(String::from_utf8_lossy(entry.path_bytes()).into_owned()),
+				change,
+			))
+		})
+		.collect())
+}
+
+/// A unified diff from `old` to `new`, both versions of the file at `path`.
+pub fn diff(old: &[u8], new: &[u8], path: &Path) -> Result<String> {
+	let diff = Patch::from_buffers(old, Some(path), new, Some(path), None)
+		.and_then(|mut patch| patch.to_buf())
+		.with_context(|| format!("Failed to diff {}", path.display()))?;
+
+	Ok(String::from_utf8_lossy(&diff).into_owned())
+}
+
+/// Combines both versions of a file, wrapping every region where they differ in conflict markers.
+pub fn merge_with_markers(existing: &[u8], generated: &[u8]) -> Result<Vec<u8>> {
+	let mut options = DiffOptions::new();
+	options.context_lines(0);
+	let patch = Patch::from_buffers(existing, None, generated, None, Some(&mut options))
+		.context("Failed to diff files")?;
+
+	let lines = existing
+		.split_inclusive(|byte| *byte == b'\n')
+		.collect::<Vec<_>>();
+	let mut output = Vec::with_capacity(existing.len() + generated.len());
+	let mut next = 0;
+
+	for hunk_idx in 0..patch.num_hunks() {
+		let (hunk, line_count) = patch.hunk(hunk_idx).context("Failed to diff files")?;
+		let old_start = hunk.old_start() as usize;
+		// Hunks that only add lines start after the line they follow.
+		let start = if hunk.old_lines() == 0 {
+			old_start
+		} else {
+			old_start.saturating_sub(1)
+		}
+		.clamp(next, lines.len());
+
+		let mut removed = Vec::new();
+		let mut added = Vec::new();
+		for line_idx in 0..line_count {
+			let line = patch
+				.line_in_hunk(hunk_idx, line_idx)
+				.context("Failed to diff files")?;
+			match line.origin() {
+				'-' => removed.extend_from_slice(line.content()),
+				'+' => added.extend_from_slice(line.content()),
+				_ => {},
+			}
+		}
+
+		output.extend(lines[next..start].concat());
+		for (marker, side) in [(&b"<<<<<<< existing\n"[..], removed), (b"=======\n", added)] {
+			output.extend_from_slice(marker);
+			output.extend_from_slice(&side);
+			if !side.is_empty() && !side.ends_with(b"\n") {
+				output.push(b'\n');
+			}
+		}
+		output.extend_from_slice(b">>>>>>> template\n");
+
+		next = (start + hunk.old_lines() as usize).min(lines.len());
+	}
+
+	output.extend(lines[next..].concat());
+	Ok(output)
+}
@@ -1,56 +1,464 @@
-use crate::hooks::{Context, Hook, Stage};
+use crate::hooks::{Context, Hook, Stage, is_inside, is_truthy, placeholders};
 use anyhow::{Context as AnyhowContext, Result};
-use std::process::{self, Command, Stdio};
+use serde::Deserialize;
+use std::{
+	collections::BTreeMap,
+	env::consts,
+	fs,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+mod process;
+mod script;
 
 pub struct RunCommands;
 
+/// An entry of `commands`, either a plain string or a `[[recipe.commands]]` table.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CommandEntry {
+	Plain(String),
+	Detailed(CommandConfig),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CommandConfig {
+	/// A shell command, run with `sh -c` (or `cmd /C` on Windows).
+	#[serde(default)]
+	run: String,
+	/// A Rhai script to run instead of a shell command.
+	#[serde(default)]
+	script: Option<String>,
+	/// A file with a Rhai script, relative to the project directory.
+	#[serde(default)]
+	script_file: Option<PathBuf>,
+	#[serde(default)]
+	stage: Option<Stage>,
+	/// Relative to the project directory.
+	#[serde(default)]
+	cwd: Option<PathBuf>,
+	#[serde(default)]
+	env: BTreeMap<String, String>,
+	/// Only runs when this variable is truthy.
+	#[serde(default)]
+	when: Option<String>,
+	/// Only runs on these operating systems (`linux`, `macos`, `windows`) or families (`unix`).
+	#[serde(default)]
+	os: Vec<String>,
+	/// Reports a failure without aborting the generation.
+	#[serde(default)]
+	allow_failure: bool,
+	/// Like `30s`, `5m` or `1h`.
+	#[serde(default)]
+	timeout: Option<String>,
+	/// Consecutive commands in the same group run at the same time.
+	#[serde(default)]
+	group: Option<String>,
+}
+
+impl From<CommandEntry> for CommandConfig {
+	fn from(entry: CommandEntry) -> Self {
+		match entry {
+			CommandEntry::Plain(run) => Self {
+				run,
+				..Self::default()
+			},
+			CommandEntry::Detailed(config) => config,
+		}
+	}
+}
+
+impl CommandConfig {
+	fn stage(&self) -> Stage {
+		self.stage.unwrap_or(Stage::Commands)
+	}
+
+	/// How the command is referred to in messages.
+	fn label(&self) -> String {
+		match (&self.script, &self.script_file) {
+			(_, Some(file)) => format!("script {}", file.display()),
+			(Some(_), None) => "inline script".to_string(),
+			(None, None) => self.run.clone(),
+		}
+	}
+
+	/// The source of the command's script, if it runs one instead of a shell command.
+	fn script(&self, project_dir: &Path) -> Result<Option<String>> {
+		match (&self.script, &self.script_file) {
+			(Some(_), Some(_)) => {
+				anyhow::bail!("Commands can't set both `script` and `script_file`")
+			},
+			(Some(_), _) | (_, Some(_)) if !self.run.is_empty() => {
+				anyhow::bail!("Command `{}` can't set both `run` and a script", self.run)
+			},
+			(Some(script), None) => Ok(Some(script.clone())),
+			(None, Some(file)) => {
+				if !is_inside(file) {
+					anyhow::bail!("Script {} must be inside the project", file.display());
+				}
+
+				fs::read_to_string(project_dir.join(file))
+					.map(Some)
+					.with_context(|| format!("Failed to read script {}", file.display()))
+			},
+			(None, None) if self.run.is_empty() => {
+				anyhow::bail!("Commands need one of `run`, `script` or `script_file`")
+			},
+			(None, None) => Ok(None),
+		}
+	}
+
+	/// Whether the command should run here, given its `os` and `when` conditions.
+	fn applies(&self, context: &Context) -> bool {
+		let os_matches = self.os.is_empty()
+			|| self
+				.os
+				.iter()
+				.any(|os| os == consts::OS || os == consts::FAMILY);
+
+		os_matches
+			&& self
+				.when
+				.as_ref()
+				.is_none_or(|key| context.variable(key).is_some_and(|value| is_truthy(&value)))
+	}
+
+	/// Renders the placeholders in `run`, quoting each value as a single shell word, and the
+	/// values of `env`.
+	fn render(self, context: &Context) -> Result<Self> {
+		let run = placeholders::render(context, &self.run, shell_quote)
+			.with_context(|| format!("Failed to render command `{}`", self.run))?;
+		let env = self
+			.env
+			.into_iter()
+			.map(|(key, value)| {
+				let value = placeholders::render(context, &value, |value| Ok(value.to_string()))
+					.with_context(|| {
+						format!("Failed to render environment variable {key} of command `{run}`")
+					})?;
+
+				Ok((key, value))
+			})
+			.collect::<Result<_>>()?;
+
+		Ok(Self { run, env, ..self })
+	}
+
+	fn timeout(&self) -> Result<Option<Duration>> {
+		self.timeout
+			.as_deref()
+			.map(|timeout| {
+				parse_duration(timeout).with_context(|| {
+					format!("Invalid timeout `{timeout}` for command `{}`", self.label())
+				})
+			})
+			.transpose()
+	}
+
+	fn cwd(&self, project_dir: &Path) -> Result<PathBuf> {
+		let Some(cwd) = &self.cwd else {
+			return Ok(project_dir.to_path_buf());
+		};
+
+		if !is_inside(cwd) {
+			anyhow::bail!(
+				"Working directory {} of command `{}` must be inside the project",
+				cwd.display(),
+				self.label()
+			);
+		}
+
+		Ok(project_dir.join(cwd))
+	}
+}
+
 impl Hook for RunCommands {
+	fn id(&self) -> &'static str {
+		"run_commands"
+	}
+
 	fn name(&self) -> &'static str {
 		"Run Commands"
 	}
 
 	fn stage(&self) -> &'static [Stage] {
-		&[Stage::PostClone]
+		// There's no project directory to run in before cloning.
+		&[
+			Stage::PostClone,
+			Stage::PrePrompt,
+			Stage::Prompt,
+			Stage::PostPrompt,
+			Stage::PreRender,
+			Stage::Render,
+			Stage::PostRender,
+			Stage::Commands,
+			Stage::PostCommands,
+			Stage::Finalize,
+			Stage::OnError,
+		]
 	}
 
 	fn run(&self, context: &Context) -> Result<()> {
-		let Some(commands) = context.recipe.config::<Vec<String>>("commands")? else {
+		let Some(commands) = context.recipe.config::<Vec<CommandEntry>>("commands")? else {
 			return Ok(());
 		};
+		let commands = commands
+			.into_iter()
+			.map(CommandConfig::from)
+			.filter(|command| command.stage() == context.stage())
+			.collect::<Vec<_>>();
 
-		for command in commands {
-			let status = run_command(&command, context)?;
-
-			if !status.success() {
-				match status.code() {
-					Some(code) => {
-						anyhow::bail!("Command `{command}` failed with exit code {code}");
-					},
-					None => {
-						anyhow::bail!("Command `{command}` terminated by signal");
-					},
+		if context.options.dry_run {
+			let commands = commands
+				.into_iter()
+				.filter(|command| command.applies(context))
+				.map(|command| command.render(context))
+				.collect::<Result<Vec<_>>>()?;
+			if commands.is_empty() {
+				return Ok(());
+			}
+
+			println!(
+				"🧪 Would run these commands in the project directory ({}):",
+				context.stage()
+			);
+			for command in &commands {
+				let cwd = command
+					.cwd
+					.as_ref()
+					.map(|cwd| format!(" (in {})", cwd.display()))
+					.unwrap_or_default();
+				let group = command
+					.group
+					.as_ref()
+					.map(|group| format!(" (alongside the rest of {group})"))
+					.unwrap_or_default();
+				println!("  - {}{cwd}{group}", command.label());
+			}
+
+			return Ok(());
+		}
+
+		// Conditions and placeholders are checked right before each command (or group), so they
+		// see the variables set by the ones before it.
+		let mut commands = commands.into_iter().peekable();
+		while let Some(command) = commands.next() {
+			let mut batch = vec![command];
+			if let Some(group) = batch[0].group.clone() {
+				while let Some(command) =
+					commands.next_if(|command| command.group.as_ref() == Some(&group))
+				{
+					batch.push(command);
 				}
 			}
+
+			let batch = batch
+				.into_iter()
+				.filter(|command| command.applies(context))
+				.map(|command| command.render(context))
+				.collect::<Result<Vec<_>>>()?;
+
+			if !batch.is_empty() {
+				context.files_changed();
+			}
+			run_batch(&batch, context)?;
 		}
 
 		Ok(())
 	}
 }
 
-fn run_command(cmd: &str, context: &Context) -> Result<process::ExitStatus> {
-	let mut command = Command::new(if cfg!(windows) { "cmd" } else { "sh" });
+/// Runs commands that were grouped together, all at the same time when there's more than one.
+fn run_batch(batch: &[CommandConfig], context: &Context) -> Result<()> {
+	let results = match batch {
+		[] => return Ok(()),
+		[command] => match command.script(context.project_dir) {
+			Ok(Some(source)) => vec![
+				command
+					.timeout()
+					.and_then(|timeout| script::run(&source, context, timeout))
+					.with_context(|| format!("Failed to run {}", command.label())),
+			],
+			Ok(None) => process::run(batch, context)?,
+			Err(err) => vec![Err(err)],
+		},
+		commands => {
+			for command in commands {
+				if command.script(context.project_dir)?.is_some() {
+					anyhow::bail!(
+						"Scripts can't run alongside other commands, but {} is in group {}",
+						command.label(),
+						command.group.as_deref().unwrap_or_default()
+					);
+				}
+			}
+
+			process::run(batch, context)?
+		},
+	};
+
+	let mut failures = Vec::new();
+	for (command, result) in batch.iter().zip(results) {
+		match result {
+			Ok(()) => context.log(format!("Ran {}", command.label())),
+			Err(err) if command.allow_failure => {
+				println!("⚠️  {err:#}. Continuing, since it's allowed to fail");
+				context.log(format!("{err:#}"));
+			},
+			Err(err) => failures.push(err),
+		}
+	}
+
+	match failures.len() {
+		0 => {},
+		1 => return Err(failures.remove(0)),
+		count => anyhow::bail!(
+			"{count} commands in group {} failed:\n{}",
+			batch[0].group.as_deref().unwrap_or_default(),
+			failures
+				.iter()
+				.map(|err| format!("  - {err:#}"))
+				.collect::<Vec<_>>()
+				.join("\n")
+		),
+	}
 
-	let command = if cfg!(windows) {
-		command.args(["/C", cmd])
+	Ok(())
+}
+
+/// What every command gets to know about the project: `NEW_VAR_<KEY>` for each variable, and
+/// where the project comes from.
+pub(super) fn environment(context: &Context) -> BTreeMap<String, String> {
+	let mut environment = context
+		.resolved_variables()
+		.into_iter()
+		.map(|(key, value)| (format!("NEW_VAR_{}", env_key(&key)), value))
+		.collect::<BTreeMap<_, _>>();
+
+	environment.insert(
+		"NEW_PROJECT_DIR".to_string(),
+		context.project_dir.display().to_string(),
+	);
+	environment.insert(
+		"NEW_PROJECT_NAME".to_string(),
+		context.project_name.to_string(),
+	);
+	environment.insert("NEW_RECIPE".to_string(), context.recipe.name.clone());
+	if let Some(sha) = &context.template_sha {
+		environment.insert("NEW_TEMPLATE_SHA".to_string(), sha.clone());
+	}
+
+	environment
+}
+
+/// Quotes a value rendered into a shell command, so it's passed as a single word and can't run
+/// anything. Values that are safe as they are, like `my-app`, are left alone.
+fn shell_quote(value: &str) -> Result<String> {
+	if !value.is_empty()
+		&& value
+			.chars()
+			.all(|char| char.is_ascii_alphanumeric() || "-_./:@+=,".contains(char))
+	{
+		return Ok(value.to_string());
+	}
+
+	if cfg!(windows) {
+		cmd_quote(value)
 	} else {
-		command.args(["-c", cmd])
+		Ok(format!("'{}'", value.replace('\'', r"'\''")))
+	}
+}
+
+/// Quotes a value for `cmd`, which expands `%VAR%` even inside quotes and has no way to escape it
+/// there, so values with `%` or `^` are refused instead.
+fn cmd_quote(value: &str) -> Result<String> {
+	if value.contains(['%', '^']) {
+		anyhow::bail!(
+			"`{value}` can't be passed to a command safely on Windows, since it contains % or ^"
+		);
+	}
+
+	Ok(format!("\"{}\"", value.replace('"', "\"\"")))
+}
+
+/// Turns a variable name like `app-id` into `APP_ID`.
+fn env_key(key: &str) -> String {
+	key.chars()
+		.map(|char| {
+			if char.is_ascii_alphanumeric() {
+				char.to_ascii_uppercase()
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+/// Parses durations like `500ms`, `30s`, `5m` or `1h`. Plain numbers are seconds.
+fn parse_duration(input: &str) -> Result<Duration> {
+	let input = input.trim();
+	let split = input
+		.find(|char: char| !char.is_ascii_digit())
+		.unwrap_or(input.len());
+	let (amount, unit) = input.split_at(split);
+	let amount = amount
+		.parse::<u64>()
+		.context("Durations must start with a number")?;
+
+	let seconds = |factor: u64| {
+		amount
+			.checked_mul(factor)
+			.map(Duration::from_secs)
+			.with_context(|| format!("The duration `{input}` is too long"))
 	};
 
-	command
-		.current_dir(context.project_dir)
-		.stdin(Stdio::inherit())
-		.stdout(Stdio::inherit())
-		.stderr(Stdio::inherit())
-		.status()
-		.with_context(|| format!("Failed to run command `{cmd}`"))
+	match unit.trim() {
+		"ms" => Ok(Duration::from_millis(amount)),
+		"" | "s" => Ok(Duration::from_secs(amount)),
+		"m" => seconds(60),
+		"h" => seconds(3600),
+		unit => anyhow::bail!("Unknown duration unit `{unit}`, use ms, s, m or h"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{cmd_quote, env_key, parse_duration, shell_quote};
+	use std::time::Duration;
+
+	#[test]
+	fn parses_durations() -> anyhow::Result<()> {
+		assert_eq!(parse_duration("500ms")?, Duration::from_millis(500));
+		assert_eq!(parse_duration("30")?, Duration::from_secs(30));
+		assert_eq!(parse_duration("5m")?, Duration::from_secs(300));
+		assert_eq!(parse_duration("1h")?, Duration::from_secs(3600));
+		assert!(parse_duration("5 days").is_err());
+		assert!(parse_duration("m").is_err());
+		assert!(parse_duration(&format!("{}h", u64::MAX / 60)).is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn quotes_values_for_the_shell() -> anyhow::Result<()> {
+		assert_eq!(shell_quote("my-app")?, "my-app");
+		assert_eq!(shell_quote("")?, "''");
+		assert_eq!(shell_quote("my app")?, "'my app'");
+		assert_eq!(shell_quote("it's; rm -rf ~")?, r"'it'\''s; rm -rf ~'");
+
+		assert_eq!(cmd_quote(r#"say "hi" & exit"#)?, r#""say ""hi"" & exit""#);
+		assert!(cmd_quote("%PATH%").is_err());
+		assert!(cmd_quote("a^&b").is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	fn turns_variable_names_into_environment_keys() {
+		assert_eq!(env_key("APP_ID"), "APP_ID");
+		assert_eq!(env_key("app-id"), "APP_ID");
+		assert_eq!(env_key("db.port"), "DB_PORT");
+	}
 }
@@ -0,0 +1,372 @@
+use crate::{
+	hooks::{
+		Context,
+		commands::{CommandConfig, environment},
+		log::Log,
+	},
+	interrupt,
+};
+use anyhow::{Context as AnyhowContext, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::{
+	io,
+	process::{self, Command, Stdio},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+/// How often running commands are checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often spinners move in quiet mode.
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+/// How much of a failed command's output is shown in quiet mode.
+const TAIL_LINES: usize = 20;
+/// How much of a command is shown in front of its output when it runs alongside others.
+const PREFIX_LENGTH: usize = 24;
+
+/// A shell command that was started, and the threads copying its output into the log.
+struct Process<'a> {
+	config: &'a CommandConfig,
+	child: process::Child,
+	deadline: Option<Instant>,
+	/// Whether the command leads its own process group.
+	grouped: bool,
+	output: Vec<JoinHandle<()>>,
+	spinner: Option<ProgressBar>,
+}
+
+/// Runs shell commands side by side, returning each one's outcome once all of them exited. The
+/// output of each is prefixed with the command when there's more than one.
+pub(super) fn run(configs: &[CommandConfig], context: &Context) -> Result<Vec<Result<()>>> {
+	let progress = MultiProgress::new();
+	let prefixes = prefixes(configs);
+
+	// Output is only captured, and kept in the log, when it's hidden or needs a prefix. Otherwise
+	// the command gets the terminal, so it can tell it's interactive.
+	let log = if context.options.quiet || prefixes.iter().any(Option::is_some) {
+		Some(context.command_log()?)
+	} else {
+		None
+	};
+	let offset = log.map(Log::len).transpose()?.unwrap_or_default();
+
+	let mut results = configs.iter().map(|_| None).collect::<Vec<_>>();
+	let mut processes = Vec::new();
+	for (index, config) in configs.iter().enumerate() {
+		match spawn(config, context, log, prefixes[index].as_deref()) {
+			Ok(mut process) => {
+				if context.options.quiet {
+					process.spinner = Some(progress.add(spinner(&config.run)));
+				}
+				processes.push((index, process));
+			},
+			Err(err) => results[index] = Some(Err(err)),
+		}
+	}
+
+	while !processes.is_empty() {
+		let mut running = Vec::new();
+		for (index, mut process) in processes {
+			match process.poll() {
+				Some(result) => {
+					if let Some(spinner) = process.spinner {
+						spinner.finish_and_clear();
+						if result.is_ok() {
+							let _ = progress.println(format!("✅ {}", process.config.run));
+						}
+					}
+					results[index] = Some(result);
+				},
+				None => running.push((index, process)),
+			}
+		}
+
+		processes = running;
+		if !processes.is_empty() {
+			thread::sleep(POLL_INTERVAL);
+		}
+	}
+
+	let results = results
+		.into_iter()
+		.map(|result| result.unwrap_or(Ok(())))
+		.collect::<Vec<_>>();
+	report_failures(
+		configs,
+		&results,
+		&prefixes,
+		log,
+		offset,
+		context.options.quiet,
+	)?;
+
+	Ok(results)
+}
+
+/// Starts a command, copying its output into `log` when given instead of leaving it the terminal.
+fn spawn<'a>(
+	config: &'a CommandConfig,
+	context: &Context,
+	log: Option<&Log>,
+	prefix: Option<&str>,
+) -> Result<Process<'a>> {
+	let cmd = &config.run;
+	let deadline = config
+		.timeout()?
+		.and_then(|timeout| Instant::now().checked_add(timeout));
+	if let Some(log) = log {
+		log.write_line(&format!("$ {cmd} ({})", context.stage()))?;
+	}
+
+	let mut command = Command::new(if cfg!(windows) { "cmd" } else { "sh" });
+
+	let command = if cfg!(windows) {
+		command.args(["/C", cmd])
+	} else {
+		command.args(["-c", cmd])
+	};
+
+	let output = || {
+		if log.is_some() {
+			Stdio::piped()
+		} else {
+			Stdio::inherit()
+		}
+	};
+	command
+		.current_dir(config.cwd(context.project_dir)?)
+		.envs(environment(context))
+		.envs(&config.env)
+		.stdin(Stdio::inherit())
+		.stdout(output())
+		.stderr(output());
+	let grouped = deadline.is_some() && isolate(command);
+
+	let mut child = command
+		.spawn()
+		.with_context(|| format!("Failed to run command `{cmd}`"))?;
+
+	let quiet = context.options.quiet;
+	let output = log.map_or_else(Vec::new, |log| {
+		vec![
+			child.stdout.take().map(|stdout| {
+				log.tee(
+					stdout,
+					prefix.map(str::to_string),
+					(!quiet).then(io::stdout),
+				)
+			}),
+			child.stderr.take().map(|stderr| {
+				log.tee(
+					stderr,
+					prefix.map(str::to_string),
+					(!quiet).then(io::stderr),
+				)
+			}),
+		]
+	});
+
+	Ok(Process {
+		config,
+		child,
+		deadline,
+		grouped,
+		output: output.into_iter().flatten().collect(),
+		spinner: None,
+	})
+}
+
+impl Process<'_> {
+	/// The command's outcome once it exited, killing it when it runs out of time.
+	fn poll(&mut self) -> Option<Result<()>> {
+		let cmd = &self.config.run;
+
+		let status = match self.child.try_wait() {
+			Ok(Some(status)) => status,
+			Ok(None)
+				if self
+					.deadline
+					.is_some_and(|deadline| Instant::now() >= deadline) =>
+			{
+				self.kill();
+				// Whatever it started may keep its output open, so that isn't waited for.
+				return Some(Err(anyhow::anyhow!(
+					"Command `{cmd}` timed out after {}",
+					self.config.timeout.as_deref().unwrap_or_default()
+				)));
+			},
+			// Commands in their own process group don't get the terminal's Ctrl-C.
+			Ok(None) if self.grouped && interrupt::check().is_err() => {
+				self.kill();
+				return Some(Err(anyhow::anyhow!("Command `{cmd}` was interrupted")));
+			},
+			Ok(None) => return None,
+			Err(err) => {
+				return Some(Err(err).with_context(|| format!("Failed to run command `{cmd}`")));
+			},
+		};
+
+		for handle in self.output.drain(..) {
+			let _ = handle.join();
+		}
+
+		Some(if status.success() {
+			Ok(())
+		} else {
+			Err(status.code().map_or_else(
+				|| anyhow::anyhow!("Command `{cmd}` terminated by signal"),
+				|code| anyhow::anyhow!("Command `{cmd}` failed with exit code {code}"),
+			))
+		})
+	}
+}
+
+impl Process<'_> {
+	/// Kills the command, along with everything it started when it leads a process group.
+	fn kill(&mut self) {
+		#[cfg(unix)]
+		if self.grouped
+			&& let Ok(pid) = i32::try_from(self.child.id())
+		{
+			// SAFETY: `kill` only sends a signal, here to the group the child was spawned into.
+			unsafe {
+				libc::kill(-pid, libc::SIGKILL);
+			}
+		}
+
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+/// Runs a command with a timeout in its own process group, so it can be killed along with
+/// everything it started. It can't read from the terminal there, so it gets no input. Returns
+/// whether it did, which is only possible on Unix.
+#[cfg(unix)]
+fn isolate(command: &mut Command) -> bool {
+	use std::os::unix::process::CommandExt;
+
+	command.process_group(0).stdin(Stdio::null());
+	true
+}
+
+#[cfg(not(unix))]
+const fn isolate(_command: &mut Command) -> bool {
+	false
+}
+
+/// Points to the log after a failure when the commands' output was kept, showing the end of that
+/// output when it wasn't shown as it ran.
+fn report_failures(
+	configs: &[CommandConfig],
+	results: &[Result<()>],
+	prefixes: &[Option<String>],
+	log: Option<&Log>,
+	offset: u64,
+	quiet: bool,
+) -> Result<()> {
+	let Some(log) = log else {
+		return Ok(());
+	};
+	let mut failed = false;
+
+	for ((config, result), prefix) in configs.iter().zip(results).zip(prefixes) {
+		let Err(err) = result else {
+			continue;
+		};
+		failed = true;
+
+		if quiet {
+			println!("📄 Last lines of output from `{}`:", config.run);
+			for line in log.tail(offset, TAIL_LINES, prefix.as_deref())? {
+				println!("  {line}");
+			}
+		}
+		log.write_line(&format!("{err}"))?;
+	}
+
+	if failed {
+		println!("📄 Full log at {}", log.path().display());
+	}
+
+	Ok(())
+}
+
+/// Marks the output of each command when it runs alongside others, like `[web: npm install] `.
+/// Commands that would look the same are numbered, so their output can't be mixed up.
+fn prefixes(configs: &[CommandConfig]) -> Vec<Option<String>> {
+	if configs.len() < 2 {
+		return vec![None; configs.len()];
+	}
+
+	let labels = configs.iter().map(label).collect::<Vec<_>>();
+	labels
+		.iter()
+		.enumerate()
+		.map(|(index, label)| {
+			if labels.iter().filter(|other| *other == label).count() > 1 {
+				Some(format!("[{label} #{}] ", index + 1))
+			} else {
+				Some(format!("[{label}] "))
+			}
+		})
+		.collect()
+}
+
+/// The command, shortened, along with where it runs when that isn't the project directory.
+fn label(config: &CommandConfig) -> String {
+	let mut label = config.run.chars().take(PREFIX_LENGTH).collect::<String>();
+	if config.run.chars().count() > PREFIX_LENGTH {
+		label.push('…');
+	}
+
+	match &config.cwd {
+		Some(cwd) => format!("{}: {label}", cwd.display()),
+		None => label,
+	}
+}
+
+#[allow(clippy::literal_string_with_formatting_args)]
+fn spinner(cmd: &str) -> ProgressBar {
+	let spinner = ProgressBar::new_spinner().with_message(cmd.to_string());
+	if let Ok(style) = ProgressStyle::with_template("{spinner} {msg}") {
+		spinner.set_style(style);
+	}
+	spinner.enable_steady_tick(SPINNER_TICK);
+
+	spinner
+}
+
+#[cfg(test)]
+mod tests {
+	use super::prefixes;
+	use crate::hooks::commands::CommandConfig;
+	use std::path::PathBuf;
+
+	fn command(run: &str, cwd: Option<&str>) -> CommandConfig {
+		CommandConfig {
+			run: run.to_string(),
+			cwd: cwd.map(PathBuf::from),
+			..CommandConfig::default()
+		}
+	}
+
+	#[test]
+	fn prefixes_tell_commands_apart() {
+		assert_eq!(prefixes(&[command("npm install", None)]), [None]);
+		assert_eq!(
+			prefixes(&[
+				command("npm install", Some("web")),
+				command("npm install", Some("api")),
+				command("npm run build -- --mode production", None),
+				command("npm run build -- --mode production-like", None),
+			]),
+			[
+				Some("[web: npm install] ".to_string()),
+				Some("[api: npm install] ".to_string()),
+				Some("[npm run build -- --mode … #3] ".to_string()),
+				Some("[npm run build -- --mode … #4] ".to_string()),
+			]
+		);
+	}
+}
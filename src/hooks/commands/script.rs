@@ -0,0 +1,197 @@
+use crate::{
+	hooks::{Context, is_inside},
+	interrupt,
+};
+use anyhow::Result;
+use inquire::{Confirm, Text};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, module_resolvers::DummyModuleResolver};
+use std::{
+	cell::RefCell,
+	collections::HashMap,
+	fs,
+	path::{Component, Path, PathBuf},
+	rc::Rc,
+	time::{Duration, Instant},
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Why a script was stopped before it finished.
+const TIMED_OUT: &str = "timed out";
+const INTERRUPTED: &str = "interrupted";
+
+/// Runs a Rhai script with access to the variables, the project's files and prompts. Files are
+/// always resolved inside the project directory, and `import` can't load any.
+pub fn run(source: &str, context: &Context, timeout: Option<Duration>) -> Result<()> {
+	let variables = Rc::new(RefCell::new(context.resolved_variables()));
+	let updates = Rc::new(RefCell::new(HashMap::new()));
+	let mut engine = Engine::new();
+	engine.set_module_resolver(DummyModuleResolver::new());
+
+	register_variables(&mut engine, &variables, &updates);
+	register_files(&mut engine, context.project_dir);
+	register_prompts(&mut engine);
+
+	let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+	engine.on_progress(move |_| {
+		if interrupt::check().is_err() {
+			Some(INTERRUPTED.into())
+		} else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+			Some(TIMED_OUT.into())
+		} else {
+			None
+		}
+	});
+
+	let mut scope = Scope::new();
+	scope.push_constant("PROJECT_NAME", context.project_name.to_string());
+	scope.push_constant("STAGE", context.stage().to_string());
+
+	let result = engine.run_with_scope(&mut scope, source);
+	for (key, value) in updates.borrow_mut().drain() {
+		context.set_variable(key, value);
+	}
+
+	match result.map_err(|err| *err) {
+		Ok(()) => Ok(()),
+		Err(EvalAltResult::ErrorTerminated(reason, _)) if reason.to_string() == TIMED_OUT => {
+			anyhow::bail!("Script timed out")
+		},
+		Err(EvalAltResult::ErrorTerminated(..)) => anyhow::bail!("Interrupted"),
+		Err(err) => anyhow::bail!("Script failed: {err}"),
+	}
+}
+
+fn register_variables(
+	engine: &mut Engine,
+	variables: &Rc<RefCell<HashMap<String, String>>>,
+	updates: &Rc<RefCell<HashMap<String, String>>>,
+) {
+	let read = Rc::clone(variables);
+	engine.register_fn("variable", move |key: &str| -> Dynamic {
+		read.borrow()
+			.get(key)
+			.map_or(Dynamic::UNIT, |value| value.clone().into())
+	});
+
+	let write = Rc::clone(variables);
+	let updates = Rc::clone(updates);
+	engine.register_fn("set_variable", move |key: &str, value: &str| {
+		write
+			.borrow_mut()
+			.insert(key.to_string(), value.to_string());
+		updates
+			.borrow_mut()
+			.insert(key.to_string(), value.to_string());
+	});
+}
+
+fn register_files(engine: &mut Engine, project_dir: &Path) {
+	let root = project_dir.to_path_buf();
+	engine.register_fn("exists", move |path: &str| -> ScriptResult<bool> {
+		Ok(resolve(&root, path)?.symlink_metadata().is_ok())
+	});
+
+	let root = project_dir.to_path_buf();
+	engine.register_fn("read_file", move |path: &str| -> ScriptResult<String> {
+		fs::read_to_string(resolve(&root, path)?)
+			.map_err(|err| format!("Failed to read {path}: {err}").into())
+	});
+
+	let root = project_dir.to_path_buf();
+	engine.register_fn(
+		"write_file",
+		move |path: &str, contents: &str| -> ScriptResult<()> {
+			let target = resolve(&root, path)?;
+			if let Some(parent) = target.parent() {
+				fs::create_dir_all(parent)
+					.map_err(|err| format!("Failed to create directory for {path}: {err}"))?;
+			}
+
+			fs::write(target, contents)
+				.map_err(|err| format!("Failed to write {path}: {err}").into())
+		},
+	);
+
+	let root = project_dir.to_path_buf();
+	engine.register_fn("rename", move |from: &str, to: &str| -> ScriptResult<()> {
+		let target = resolve(&root, to)?;
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent)
+				.map_err(|err| format!("Failed to create directory for {to}: {err}"))?;
+		}
+
+		fs::rename(resolve(&root, from)?, target)
+			.map_err(|err| format!("Failed to rename {from} to {to}: {err}").into())
+	});
+
+	let root = project_dir.to_path_buf();
+	engine.register_fn("delete", move |path: &str| -> ScriptResult<()> {
+		let target = resolve(&root, path)?;
+		if target.is_dir() {
+			fs::remove_dir_all(target)
+		} else if target.symlink_metadata().is_ok() {
+			fs::remove_file(target)
+		} else {
+			Ok(())
+		}
+		.map_err(|err| format!("Failed to delete {path}: {err}").into())
+	});
+}
+
+fn register_prompts(engine: &mut Engine) {
+	engine.register_fn("prompt", |message: &str| -> ScriptResult<String> {
+		Text::new(message)
+			.prompt()
+			.map_err(|err| format!("Failed to prompt: {err}").into())
+	});
+
+	engine.register_fn("confirm", |message: &str| -> ScriptResult<bool> {
+		Confirm::new(message)
+			.prompt()
+			.map_err(|err| format!("Failed to prompt: {err}").into())
+	});
+}
+
+/// Resolves `path` inside the project, refusing anything that would leave it, like `..` or a
+/// symlink along the way, and the project directory itself.
+fn resolve(project_dir: &Path, path: &str) -> ScriptResult<PathBuf> {
+	let relative = Path::new(path);
+	if !is_inside(relative)
+		|| !relative
+			.components()
+			.any(|component| matches!(component, Component::Normal(_)))
+	{
+		return Err(format!("`{path}` isn't a path inside the project").into());
+	}
+
+	let root = project_dir
+		.canonicalize()
+		.map_err(|err| format!("Failed to resolve the project directory: {err}"))?;
+	let mut target = root.clone();
+	for component in relative.components() {
+		let Component::Normal(name) = component else {
+			continue;
+		};
+
+		target.push(name);
+		if target.is_symlink() {
+			return Err(
+				format!("{path} goes through a symlink, which scripts can't follow").into(),
+			);
+		}
+	}
+
+	// Whatever part of it exists already must still be inside the project once resolved.
+	let existing = target
+		.ancestors()
+		.find(|ancestor| ancestor.exists())
+		.map(Path::canonicalize)
+		.transpose()
+		.map_err(|err| format!("Failed to resolve {path}: {err}"))?;
+	if existing.is_none_or(|existing| !existing.starts_with(&root)) {
+		return Err(format!("{path} is outside of the project").into());
+	}
+
+	Ok(target)
+}
@@ -0,0 +1,42 @@
+use crate::{
+	git,
+	hooks::{Context, Hook, Stage},
+};
+use anyhow::{Context as AnyhowContext, Result};
+use std::fs;
+
+/// Leaves out paths marked `export-ignore` in the template's `.gitattributes`, like `git archive`.
+pub struct RemoveExportIgnored;
+
+impl Hook for RemoveExportIgnored {
+	fn id(&self) -> &'static str {
+		"remove_export_ignored"
+	}
+
+	fn name(&self) -> &'static str {
+		"Remove export-ignore paths from template"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::PostClone]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		if !context.project_dir.join(".git").is_dir() {
+			return Ok(());
+		}
+
+		for relative in git::export_ignored(context.project_dir)? {
+			let path = context.project_dir.join(&relative);
+
+			if path.is_dir() {
+				fs::remove_dir_all(&path)
+			} else {
+				fs::remove_file(&path)
+			}
+			.with_context(|| format!("Failed to remove {}", relative.display()))?;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,40 @@
+use crate::{
+	git,
+	hooks::{Context, Hook, Stage},
+};
+use anyhow::Result;
+
+/// Fills `AUTHOR` and `AUTHOR_EMAIL` from the user's git configuration, unless the recipe or an
+/// earlier hook already set them.
+pub struct GitIdentity;
+
+impl Hook for GitIdentity {
+	fn id(&self) -> &'static str {
+		"git_identity"
+	}
+
+	fn name(&self) -> &'static str {
+		"Read git identity"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::PrePrompt]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		// A missing or broken git configuration just leaves them to the recipe or a prompt.
+		let Ok(identity) = git::identity() else {
+			return Ok(());
+		};
+
+		for (key, value) in [("AUTHOR", identity.name), ("AUTHOR_EMAIL", identity.email)] {
+			if let Some(value) = value
+				&& context.variable(key).is_none()
+			{
+				context.set_variable(key, value);
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, BufRead, BufReader, Read, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	thread::{self, JoinHandle},
+};
+
+/// The full output of the commands a generation ran with their output captured, kept under
+/// `${state_dir}/new/logs/<timestamp>-<recipe>.log`.
+pub struct Log {
+	path: PathBuf,
+	file: Arc<Mutex<File>>,
+}
+
+/// Where logs are kept.
+pub fn default_dir() -> Result<PathBuf> {
+	let state_dir = dirs::state_dir()
+		.or_else(dirs::data_local_dir)
+		.context("Unable to resolve state directory")?;
+
+	Ok(state_dir.join("new").join("logs"))
+}
+
+impl Log {
+	/// Creates a new log in `logs_dir`. Generations of the same recipe started within the same
+	/// second get a numbered log each, like `<timestamp>-<recipe>-2.log`.
+	pub fn create(logs_dir: &Path, recipe: &str) -> Result<Self> {
+		fs::create_dir_all(logs_dir)
+			.with_context(|| format!("Failed to create logs directory {}", logs_dir.display()))?;
+
+		let stem = format!(
+			"{}-{}",
+			Local::now().format("%Y%m%d-%H%M%S"),
+			recipe.replace(['/', '\\'], "-")
+		);
+		for number in 1.. {
+			let path = if number == 1 {
+				logs_dir.join(format!("{stem}.log"))
+			} else {
+				logs_dir.join(format!("{stem}-{number}.log"))
+			};
+
+			match OpenOptions::new().append(true).create_new(true).open(&path) {
+				Ok(file) => {
+					return Ok(Self {
+						path,
+						file: Arc::new(Mutex::new(file)),
+					});
+				},
+				Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+				Err(err) => {
+					return Err(err)
+						.with_context(|| format!("Failed to create log file {}", path.display()));
+				},
+			}
+		}
+
+		unreachable!("ran out of log file numbers")
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// How much has been written so far, to read back what one command printed.
+	pub fn len(&self) -> Result<u64> {
+		Ok(self.file.lock().expect("poisoned").metadata()?.len())
+	}
+
+	pub fn write_line(&self, line: &str) -> Result<()> {
+		writeln!(self.file.lock().expect("poisoned"), "{line}")
+			.with_context(|| format!("Failed to write log file {}", self.path.display()))
+	}
+
+	/// Copies everything `output` prints into the log, and to `echo` when given. With a `prefix`,
+	/// it's copied line by line with the prefix in front, to tell it apart from the output of
+	/// commands running alongside.
+	pub fn tee(
+		&self,
+		output: impl Read + Send + 'static,
+		prefix: Option<String>,
+		mut echo: Option<impl Write + Send + 'static>,
+	) -> JoinHandle<()> {
+		let file = Arc::clone(&self.file);
+
+		thread::spawn(move || {
+			let mut reader = BufReader::new(output);
+
+			loop {
+				let chunk = match &prefix {
+					Some(prefix) => {
+						let mut line = prefix.clone().into_bytes();
+						match reader.read_until(b'\n', &mut line) {
+							Ok(0) | Err(_) => break,
+							Ok(_) if !line.ends_with(b"\n") => {
+								line.push(b'\n');
+								line
+							},
+							Ok(_) => line,
+						}
+					},
+					// Unprefixed output is copied as it comes, so prompts without a newline show up.
+					None => match reader.fill_buf() {
+						Ok([]) | Err(_) => break,
+						Ok(buffer) => {
+							let chunk = buffer.to_vec();
+							reader.consume(chunk.len());
+							chunk
+						},
+					},
+				};
+
+				let _ = file.lock().expect("poisoned").write_all(&chunk);
+				if let Some(echo) = &mut echo {
+					let _ = echo.write_all(&chunk).and_then(|()| echo.flush());
+				}
+			}
+		})
+	}
+
+	/// The last `count` lines written since `offset`, only those starting with `prefix` if given.
+	pub fn tail(&self, offset: u64, count: usize, prefix: Option<&str>) -> Result<Vec<String>> {
+		let contents = fs::read(&self.path)
+			.with_context(|| format!("Failed to read log file {}", self.path.display()))?;
+		let start = usize::try_from(offset)
+			.unwrap_or(usize::MAX)
+			.min(contents.len());
+		let output = String::from_utf8_lossy(&contents[start..]);
+		let lines = output
+			.lines()
+			.filter_map(|line| prefix.map_or(Some(line), |prefix| line.strip_prefix(prefix)))
+			.collect::<Vec<_>>();
+
+		Ok(lines[lines.len().saturating_sub(count)..]
+			.iter()
+			.map(ToString::to_string)
+			.collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Log;
+	use std::io::Cursor;
+
+	#[test]
+	fn tees_output_and_reads_back_the_tail() -> anyhow::Result<()> {
+		let logs_dir = tempfile::Builder::new().prefix("new-cli-logs").tempdir()?;
+		let log = Log::create(logs_dir.path(), "log-test")?;
+		let other = Log::create(logs_dir.path(), "log-test")?;
+		assert!(log.path().to_string_lossy().ends_with("-log-test.log"));
+		assert_ne!(log.path(), other.path());
+
+		log.write_line("$ first")?;
+		let offset = log.len()?;
+
+		log.tee(
+			Cursor::new(b"one\ntwo\nthree\n".to_vec()),
+			None,
+			None::<Vec<u8>>,
+		)
+		.join()
+		.expect("tee panicked");
+		log.tee(
+			Cursor::new(b"four\nfive".to_vec()),
+			Some("[b] ".to_string()),
+			None::<Vec<u8>>,
+		)
+		.join()
+		.expect("tee panicked");
+
+		assert_eq!(log.tail(offset, 2, None)?, ["[b] four", "[b] five"]);
+		assert_eq!(log.tail(offset, 10, Some("[b] "))?, ["four", "five"]);
+		assert_eq!(
+			log.tail(0, 10, None)?,
+			["$ first", "one", "two", "three", "[b] four", "[b] five"]
+		);
+
+		Ok(())
+	}
+}
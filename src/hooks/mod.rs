@@ -1,42 +1,276 @@
 mod commands;
+mod export_ignore;
+mod git_identity;
+mod log;
 mod placeholders;
+mod plugins;
+mod provenance;
 mod remove_git;
+mod requirements;
+mod template_files;
+mod template_scripts;
 
-use crate::{hooks::commands::RunCommands, recipes::Recipe};
+use crate::{interrupt, recipes::Recipe};
 use anyhow::{Context as AnyhowContext, Result};
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::{
+	cell::{Cell, OnceCell, RefCell},
+	collections::{HashMap, HashSet},
+	fmt,
+	path::{Component, Path},
+};
 
-pub use placeholders::ReplacePlaceholders;
+pub use commands::RunCommands;
+pub use export_ignore::RemoveExportIgnored;
+pub use git_identity::GitIdentity;
+pub use placeholders::{PromptPlaceholders, ReplacePlaceholders};
+pub use plugins::Plugin;
+pub use provenance::WriteProvenance;
 pub use remove_git::RemoveGit;
+pub use requirements::CheckRequirements;
+pub use template_files::{ApplyTemplateFiles, RemoveTemplateFiles};
+pub use template_scripts::{TemplateScripts, is_trusted};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A point in the pipeline. Built-in hooks run in `Prompt`, `Render` and `Commands`, and the
+/// stages around them are there for recipes to hook into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Stage {
 	PreClone,
 	PostClone,
+	PrePrompt,
+	Prompt,
+	PostPrompt,
+	PreRender,
+	Render,
+	PostRender,
+	Commands,
+	PostCommands,
+	Finalize,
+	/// Runs instead of the remaining stages when one of them fails.
+	OnError,
+}
+
+impl Stage {
+	/// The stages that turn a freshly cloned template into the project's files, in order.
+	pub const RENDER: &[Self] = &[
+		Self::PostClone,
+		Self::PrePrompt,
+		Self::Prompt,
+		Self::PostPrompt,
+		Self::PreRender,
+		Self::Render,
+		Self::PostRender,
+	];
+
+	/// The stages that run once the project's files are in place, in order.
+	pub const FINISH: &[Self] = &[Self::Commands, Self::PostCommands, Self::Finalize];
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			Self::PreClone => "pre_clone",
+			Self::PostClone => "post_clone",
+			Self::PrePrompt => "pre_prompt",
+			Self::Prompt => "prompt",
+			Self::PostPrompt => "post_prompt",
+			Self::PreRender => "pre_render",
+			Self::Render => "render",
+			Self::PostRender => "post_render",
+			Self::Commands => "commands",
+			Self::PostCommands => "post_commands",
+			Self::Finalize => "finalize",
+			Self::OnError => "on_error",
+		};
+
+		write!(f, "{name}")
+	}
+}
+
+/// Flags from the command line that change how hooks behave.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+	/// Fail when placeholders are left without a value.
+	pub strict: bool,
+	/// Preview the project without running commands. The template's repository is kept, so the
+	/// preview can show what changed.
+	pub dry_run: bool,
+	/// Show a spinner for each command instead of its output, which still goes to the log.
+	pub quiet: bool,
+	/// Whether the template's own code may run: its scripts, and the commands and plugins its
+	/// manifest declares. Asked for when not set.
+	pub trust_template: Option<bool>,
+}
+
+/// Something a hook did, kept for later hooks and the provenance record.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+	pub stage: Stage,
+	pub hook: String,
+	pub message: String,
 }
 
 pub struct Context<'a> {
 	pub recipe: &'a Recipe,
+	pub options: &'a Options,
 	pub project_dir: &'a Path,
 	pub project_name: &'a str,
+	/// The recipe's `[recipe.replacements]`, parsed once.
+	replacements: HashMap<String, String>,
+	/// Values gathered by earlier hooks, like prompted placeholders.
+	pub variables: RefCell<HashMap<String, String>>,
+	/// Placeholders the user chose to leave without a value, so they aren't asked for again.
+	pub declined: RefCell<HashSet<String>>,
+	/// The commit the template was cloned at.
+	pub template_sha: Option<String>,
+	/// What the hooks did so far, in order.
+	pub events: RefCell<Vec<Event>>,
+	stage: Cell<Stage>,
+	/// The id of the hook that is currently running.
+	hook: RefCell<String>,
+	/// Created when the first command with captured output runs.
+	command_log: OnceCell<log::Log>,
+	/// What the prompt stage found in the project's files, until a hook changes them.
+	placeholder_scan: RefCell<Option<placeholders::Scan>>,
 }
 
 impl<'a> Context<'a> {
-	pub const fn new(recipe: &'a Recipe, project_dir: &'a Path, project_name: &'a str) -> Self {
-		Self {
+	pub fn new(
+		recipe: &'a Recipe,
+		options: &'a Options,
+		project_dir: &'a Path,
+		project_name: &'a str,
+	) -> Result<Self> {
+		let replacements = recipe
+			.config::<HashMap<String, String>>("replacements")?
+			.unwrap_or_default();
+
+		Ok(Self {
 			recipe,
+			options,
 			project_dir,
 			project_name,
+			replacements,
+			variables: RefCell::new(HashMap::new()),
+			declined: RefCell::new(HashSet::new()),
+			template_sha: None,
+			events: RefCell::new(Vec::new()),
+			stage: Cell::new(Stage::PreClone),
+			hook: RefCell::new(String::new()),
+			command_log: OnceCell::new(),
+			placeholder_scan: RefCell::new(None),
+		})
+	}
+
+	pub fn with_template_sha(self, sha: String) -> Self {
+		Self {
+			template_sha: Some(sha),
+			..self
+		}
+	}
+
+	/// Every value known so far: `[recipe.replacements]`, `NAME`, and what earlier hooks gathered.
+	pub fn resolved_variables(&self) -> HashMap<String, String> {
+		let mut variables = self.replacements.clone();
+		variables
+			.entry("NAME".to_string())
+			.or_insert_with(|| self.project_name.to_string());
+		variables.extend(self.variables.borrow().clone());
+
+		variables
+	}
+
+	/// The value of `key`, as gathered by earlier hooks or set in `[recipe.replacements]`.
+	pub fn variable(&self, key: &str) -> Option<String> {
+		self.variables
+			.borrow()
+			.get(key)
+			.or_else(|| self.replacements.get(key))
+			.cloned()
+			.or_else(|| (key == "NAME").then(|| self.project_name.to_string()))
+	}
+
+	/// Sets `key` for the hooks that run later, recording it when the value changes.
+	pub fn set_variable(&self, key: impl Into<String>, value: impl Into<String>) {
+		let (key, value) = (key.into(), value.into());
+		if self.variable(&key).as_ref() != Some(&value) {
+			self.log(format!("Set {key}"));
+		}
+
+		self.variables.borrow_mut().insert(key, value);
+	}
+
+	/// Records something the current hook did.
+	pub fn log(&self, message: impl Into<String>) {
+		self.events.borrow_mut().push(Event {
+			stage: self.stage(),
+			hook: self.hook.borrow().clone(),
+			message: message.into(),
+		});
+	}
+
+	/// Where the output of commands goes, created the first time it's needed.
+	pub fn command_log(&self) -> Result<&log::Log> {
+		if let Some(log) = self.command_log.get() {
+			return Ok(log);
+		}
+
+		let log = log::Log::create(&log::default_dir()?, &self.recipe.name)?;
+		Ok(self.command_log.get_or_init(|| log))
+	}
+
+	/// Forgets what was found in the project's files, after a hook may have changed them.
+	fn files_changed(&self) {
+		self.placeholder_scan.take();
+	}
+
+	/// The stage that is currently running.
+	pub const fn stage(&self) -> Stage {
+		self.stage.get()
+	}
+
+	/// Keeps everything gathered so far, for hooks that run after the project was moved.
+	pub fn moved_to(self, project_dir: &'a Path) -> Self {
+		Self {
+			project_dir,
+			..self
 		}
 	}
 }
 
+/// Whether a value answers yes, like `true`, `yes` or `1`.
+pub fn is_truthy(value: &str) -> bool {
+	["true", "yes", "y", "on", "1"]
+		.iter()
+		.any(|truthy| value.trim().eq_ignore_ascii_case(truthy))
+}
+
+/// Whether `path` is relative and stays inside the directory it's joined to.
+pub fn is_inside(path: &Path) -> bool {
+	path.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
 pub trait Hook {
-	fn stage(&self) -> &'static [Stage];
-	fn name(&self) -> &'static str;
+	fn stage(&self) -> &[Stage];
+	/// How recipes refer to the hook under `[recipe.hooks]`.
+	fn id(&self) -> &str;
+	fn name(&self) -> &str;
 	fn run(&self, context: &Context) -> Result<()>;
 }
 
+/// `[recipe.hooks]`, enabling or disabling built-in hooks by id and reordering them.
+#[derive(Debug, Default, Deserialize)]
+struct PipelineConfig {
+	/// Hooks to run in this order, in the slots they already take.
+	#[serde(default)]
+	order: Vec<String>,
+	#[serde(flatten)]
+	enabled: HashMap<String, bool>,
+}
+
 pub struct Registry {
 	hooks: Vec<Box<dyn Hook>>,
 }
@@ -49,24 +283,197 @@ impl Registry {
 	pub fn with_defaults() -> Self {
 		let mut registry = Self::new();
 
-		registry.register(RemoveGit);
+		registry.register(CheckRequirements);
+		// Hooks reading the template's `.gitattributes` run before the repository is removed.
+		registry.register(RemoveExportIgnored);
+		registry.register(RemoveTemplateFiles);
+		registry.register(GitIdentity);
+		registry.register(PromptPlaceholders);
 		registry.register(ReplacePlaceholders);
+		registry.register(ApplyTemplateFiles);
+		registry.register(RemoveGit);
+		registry.register(TemplateScripts);
 		registry.register(RunCommands);
+		registry.register(WriteProvenance);
 
 		registry
 	}
 
+	/// The default hooks and enabled plugins, enabled and ordered as configured under
+	/// `[recipe.hooks]`.
+	pub fn for_recipe(recipe: &Recipe) -> Result<Self> {
+		let mut registry = Self::with_defaults();
+		for plugin in Plugin::load(recipe)? {
+			if registry.hooks.iter().any(|hook| hook.id() == plugin.id()) {
+				anyhow::bail!(
+					"Plugin {} has the same name as a built-in hook",
+					plugin.id()
+				);
+			}
+			registry.register(plugin);
+		}
+		let Some(config) = recipe.config::<PipelineConfig>("hooks")? else {
+			return Ok(registry);
+		};
+
+		for id in config.enabled.keys().chain(&config.order) {
+			if !registry.hooks.iter().any(|hook| hook.id() == id) {
+				anyhow::bail!("Unknown hook {id} in recipe.hooks");
+			}
+		}
+
+		registry
+			.hooks
+			.retain(|hook| config.enabled.get(hook.id()).copied().unwrap_or(true));
+
+		let mut order = Vec::new();
+		for id in &config.order {
+			if order.contains(&id.as_str()) {
+				anyhow::bail!("Hook {id} is listed twice in recipe.hooks.order");
+			}
+			if registry.hooks.iter().any(|hook| hook.id() == id) {
+				order.push(id.as_str());
+			}
+		}
+
+		let slots = registry
+			.hooks
+			.iter()
+			.enumerate()
+			.filter(|(_, hook)| order.contains(&hook.id()))
+			.map(|(index, _)| index)
+			.collect::<Vec<_>>();
+
+		let mut hooks = registry.hooks.drain(..).enumerate().collect::<Vec<_>>();
+		hooks.sort_by_key(|(index, hook)| {
+			order
+				.iter()
+				.position(|id| *id == hook.id())
+				.map_or(*index, |rank| slots[rank])
+		});
+		registry.hooks = hooks.into_iter().map(|(_, hook)| hook).collect();
+
+		Ok(registry)
+	}
+
 	pub fn register<H: Hook + 'static>(&mut self, hook: H) {
 		self.hooks.push(Box::new(hook));
 	}
 
 	pub fn run(&self, stage: Stage, context: &Context) -> Result<()> {
+		context.stage.set(stage);
+
 		self.hooks
 			.iter()
 			.filter(|hook| hook.stage().contains(&stage))
 			.try_for_each(|hook| {
-				hook.run(context)
-					.with_context(|| format!("🔴 {} FAILED", hook.name()))
+				if stage != Stage::OnError {
+					interrupt::check()?;
+				}
+
+				Self::run_hook(hook.as_ref(), context)
 			})
 	}
+
+	/// Runs the hook with `id` on its own as part of `stage`, unless the recipe disabled it.
+	pub fn run_one(&self, id: &str, stage: Stage, context: &Context) -> Result<()> {
+		context.stage.set(stage);
+
+		self.hooks
+			.iter()
+			.find(|hook| hook.id() == id)
+			.map_or(Ok(()), |hook| Self::run_hook(hook.as_ref(), context))
+	}
+
+	fn run_hook(hook: &dyn Hook, context: &Context) -> Result<()> {
+		hook.id().clone_into(&mut context.hook.borrow_mut());
+		hook.run(context)
+			.with_context(|| format!("🔴 {} FAILED", hook.name()))
+	}
+
+	/// Runs `stages` in order. If one fails, the `OnError` hooks run before the error is returned.
+	pub fn run_stages(&self, stages: &[Stage], context: &Context) -> Result<()> {
+		let Err(err) = stages
+			.iter()
+			.try_for_each(|stage| self.run(*stage, context))
+		else {
+			return Ok(());
+		};
+
+		self.run_on_error(context);
+		Err(err)
+	}
+
+	/// Runs the `OnError` hooks, reporting their own failures without hiding the original one.
+	pub fn run_on_error(&self, context: &Context) {
+		if let Err(err) = self.run(Stage::OnError, context) {
+			eprintln!("{err:#}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Context, Options, Registry};
+	use crate::recipes::{Kind, Recipe};
+	use std::path::Path;
+
+	#[test]
+	fn for_recipe_disables_and_reorders_hooks() -> anyhow::Result<()> {
+		let recipe = Recipe {
+			name: "local".to_string(),
+			repo: "owner/repo".to_string(),
+			branch: None,
+			kind: Kind::Project,
+			extra: toml::from_str(
+				r#"hooks = { remove_template_files = false, order = ["remove_git", "replace_placeholders"] }"#,
+			)?,
+		};
+
+		let registry = Registry::for_recipe(&recipe)?;
+		let ids = registry
+			.hooks
+			.iter()
+			.map(|hook| hook.id())
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			ids,
+			[
+				"check_requirements",
+				"remove_export_ignored",
+				"git_identity",
+				"prompt_placeholders",
+				"remove_git",
+				"apply_template_files",
+				"replace_placeholders",
+				"template_scripts",
+				"run_commands",
+				"write_provenance",
+			]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn context_rejects_replacements_that_arent_strings() -> anyhow::Result<()> {
+		let recipe = Recipe {
+			name: "local".to_string(),
+			repo: "owner/repo".to_string(),
+			branch: None,
+			kind: Kind::Project,
+			extra: toml::from_str("replacements = { PORT = 3000 }")?,
+		};
+
+		let options = Options::default();
+		let result = Context::new(&recipe, &options, Path::new("."), "MyProject");
+		assert!(
+			result.is_err_and(|err| {
+				err.to_string() == "Failed to parse recipe.replacements config"
+			})
+		);
+
+		Ok(())
+	}
 }
@@ -1,82 +1,390 @@
-use crate::hooks::{Context, Hook, Stage, placeholders::replacer::Replacer};
-use anyhow::{Context as AnyhowContext, Result};
-use ignore::WalkBuilder;
-use inquire::Text;
-use regex::Regex;
-use std::{
-	collections::{HashMap, HashSet},
-	ffi::OsStr,
-	fs,
-	path::Path,
-	sync::LazyLock,
+use crate::{
+	git,
+	hooks::{
+		Context, Hook, Stage, is_truthy,
+		placeholders::{
+			encoding::{Encoding, Encodings},
+			replacer::{RegexReplacement, Replacer},
+			scan::Scanner,
+			syntax::{Delimiters, Syntax},
+		},
+		template_files,
+	},
 };
-
+use anyhow::{Context as AnyhowContext, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::GitignoreBuilder;
+use inquire::{Confirm, Text};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+mod case;
+mod encoding;
 mod replacer;
+mod report;
+mod scan;
+mod syntax;
+
+pub(super) use scan::Scan;
+
+/// Renders `template` with the recipe's placeholder syntax and every variable resolved so far,
+/// passing each value through `escape`. Placeholders for unknown keys are left as written.
+pub fn render(
+	context: &Context,
+	template: &str,
+	escape: impl Fn(&str) -> Result<String>,
+) -> Result<String> {
+	let config = ReplacePlaceholders::load_config(context)?;
+	let syntax = Syntax::from_config(config.delimiters.as_ref())?;
+
+	syntax.render_known(template, &context.resolved_variables(), escape)
+}
+
+/// Asks for the value of every placeholder in the template, before anything is rendered.
+pub struct PromptPlaceholders;
 
 pub struct ReplacePlaceholders;
 
+#[derive(Debug, Default, Deserialize)]
+struct Config {
+	#[serde(default)]
+	delimiters: Option<Delimiters>,
+	/// Globs for files whose contents are left untouched, like `.github/**`.
+	#[serde(default)]
+	exclude: Vec<String>,
+	/// Whether values containing `/` may rename an entry into nested directories.
+	#[serde(default)]
+	nested_paths: bool,
+	/// Encodings for files without a BOM, like `"**/*.rc" = "utf-16le"`.
+	#[serde(default)]
+	encodings: BTreeMap<String, Encoding>,
+}
+
+/// A `[[recipe.regex_replacements]]` entry.
+#[derive(Debug, Deserialize)]
+struct RegexReplacementConfig {
+	pattern: String,
+	/// Can use capture groups (`$1`, `${name}`) and placeholders (`{{KEY}}`).
+	replacement: String,
+	#[serde(default)]
+	files: Option<String>,
+}
+
+/// A `[[recipe.conditional_files]]` entry, removing `paths` unless the `when` value is truthy.
+#[derive(Debug, Deserialize)]
+struct ConditionalFilesConfig {
+	when: String,
+	/// Gitignore-style patterns, like `docker/` or `/Dockerfile`.
+	paths: Vec<String>,
+}
+
+impl Config {
+	fn exclude_set(&self) -> Result<GlobSet> {
+		let mut builder = GlobSetBuilder::new();
+		for pattern in &self.exclude {
+			builder.add(
+				Glob::new(pattern)
+					.with_context(|| format!("Invalid placeholder exclude glob {pattern}"))?,
+			);
+		}
+
+		builder
+			.build()
+			.context("Failed to build placeholder exclude globs")
+	}
+}
+
+/// Everything loaded from the recipe, ready to scan the project and render it.
+struct Template {
+	config: Config,
+	syntax: Syntax,
+	exclude: GlobSet,
+	encodings: Encodings,
+	renames: HashMap<String, String>,
+	regex_replacements: Vec<RegexReplacement>,
+}
+
+impl Template {
+	fn load(context: &Context) -> Result<Self> {
+		let config = ReplacePlaceholders::load_config(context)?;
+		let syntax = Syntax::from_config(config.delimiters.as_ref())?;
+		let exclude = config.exclude_set()?;
+		let encodings = Encodings::new(&config.encodings)?;
+		let renames = ReplacePlaceholders::load_renames(context)?;
+		let regex_replacements = ReplacePlaceholders::load_regex_replacements(context)?
+			.into_iter()
+			.map(|config| {
+				RegexReplacement::new(&config.pattern, config.replacement, config.files.as_deref())
+			})
+			.collect::<Result<Vec<_>>>()
+			.context("Failed to load recipe.regex_replacements")?;
+
+		Ok(Self {
+			config,
+			syntax,
+			exclude,
+			encodings,
+			renames,
+			regex_replacements,
+		})
+	}
+
+	/// Walks the project once, finding every placeholder and what may need rewriting.
+	fn scan(&self, context: &Context) -> Result<Scan> {
+		let binary = if context.project_dir.join(".git").is_dir() {
+			git::binary_paths(context.project_dir)?
+		} else {
+			HashSet::new()
+		};
+
+		Scanner::new(
+			&self.syntax,
+			&self.exclude,
+			&self.encodings,
+			&binary,
+			self.renames.keys(),
+			&self.regex_replacements,
+		)?
+		.scan(context.project_dir)
+	}
+
+	/// Every key the project and the recipe's templates refer to.
+	fn keys(&self, scan: &Scan) -> HashSet<String> {
+		let mut keys = scan.keys.clone();
+		for template in self.renames.values() {
+			self.syntax.template_keys(template, &mut keys);
+		}
+		for regex_replacement in &self.regex_replacements {
+			self.syntax
+				.template_keys(regex_replacement.template(), &mut keys);
+		}
+
+		keys
+	}
+}
+
+impl Hook for PromptPlaceholders {
+	fn id(&self) -> &'static str {
+		"prompt_placeholders"
+	}
+
+	fn name(&self) -> &'static str {
+		"Prompt for Placeholders"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::Prompt]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		let template = Template::load(context)?;
+		let mut replacements = context.resolved_variables();
+		ReplacePlaceholders::remove_conditional_files(context, &mut replacements, true)?;
+
+		let scan = template.scan(context)?;
+		let mut found = template.keys(&scan);
+		found.retain(|key| !context.declined.borrow().contains(key));
+		let declined =
+			ReplacePlaceholders::prompt_for_missing_placeholders(found, &mut replacements)?;
+		context.declined.borrow_mut().extend(declined);
+
+		for (key, value) in replacements {
+			context.set_variable(key, value);
+		}
+		// Rendering reuses the scan, unless a hook changes the files in between.
+		context.placeholder_scan.replace(Some(scan));
+
+		Ok(())
+	}
+}
+
 impl Hook for ReplacePlaceholders {
+	fn id(&self) -> &'static str {
+		"replace_placeholders"
+	}
+
 	fn name(&self) -> &'static str {
 		"Replace Placeholders"
 	}
 
 	fn stage(&self) -> &'static [Stage] {
-		&[Stage::PostClone]
+		&[Stage::Render]
 	}
 
 	fn run(&self, context: &Context) -> Result<()> {
-		let mut replacements = Self::load_config(context)?;
+		let template = Template::load(context)?;
+		let mut replacements = context.resolved_variables();
+
+		// Without a scan from the prompt stage, nothing is asked: placeholders without a value
+		// are reported as leftovers instead.
+		let scan = if let Some(scan) = context.placeholder_scan.take() {
+			scan
+		} else {
+			Self::remove_conditional_files(context, &mut replacements, false)?;
+			template.scan(context)?
+		};
+
+		let Template {
+			config,
+			syntax,
+			encodings,
+			renames,
+			regex_replacements,
+			..
+		} = template;
+		let known = replacements.keys().cloned().collect::<HashSet<_>>();
+
+		let renames = renames
+			.into_iter()
+			.map(|(literal, template)| Ok((literal, syntax.render(&template, &replacements)?)))
+			.collect::<Result<Vec<_>>>()
+			.context("Failed to render recipe.rename values")?;
+
+		let replacer = Replacer::new(
+			replacements,
+			&renames,
+			regex_replacements,
+			&syntax,
+			encodings,
+			config.nested_paths,
+		)?;
+		let mut report = replacer.apply(context.project_dir, &scan)?;
+
+		for (path, keys) in &scan.placeholders {
+			let missing = keys.difference(&known).cloned().collect::<HashSet<_>>();
+			if !missing.is_empty() {
+				let relative = path.strip_prefix(context.project_dir).unwrap_or(path);
+				report.add_leftovers(relative, &missing);
+			}
+		}
 
-		replacements
-			.entry("NAME".to_string())
-			.or_insert_with(|| context.project_name.to_string());
+		print!("{report}");
+		for key in report.leftovers.keys() {
+			context.log(format!("Left {key} without a value"));
+		}
 
-		Self::prompt_for_missing_placeholders(context, &mut replacements)?;
+		if context.options.strict && !report.leftovers.is_empty() {
+			anyhow::bail!(
+				"Placeholders left without a value: {}",
+				report
+					.leftovers
+					.keys()
+					.cloned()
+					.collect::<Vec<_>>()
+					.join(", ")
+			);
+		}
 
-		let replacer = Replacer::new(replacements)?;
-		replacer.apply(context.project_dir)
+		Ok(())
 	}
 }
 
 impl ReplacePlaceholders {
-	fn load_config(context: &Context) -> Result<HashMap<String, String>> {
-		let replacements = context
+	fn load_config(context: &Context) -> Result<Config> {
+		Ok(context
+			.recipe
+			.config::<Config>("placeholders")?
+			.unwrap_or_default())
+	}
+
+	fn load_renames(context: &Context) -> Result<HashMap<String, String>> {
+		let renames = context
 			.recipe
-			.config::<HashMap<String, String>>("replacements")?
+			.config::<HashMap<String, String>>("rename")?
 			.unwrap_or_default();
 
-		Ok(replacements)
+		Ok(renames)
 	}
 
-	fn prompt_for_missing_placeholders(
+	fn load_regex_replacements(context: &Context) -> Result<Vec<RegexReplacementConfig>> {
+		let regex_replacements = context
+			.recipe
+			.config::<Vec<RegexReplacementConfig>>("regex_replacements")?
+			.unwrap_or_default();
+
+		Ok(regex_replacements)
+	}
+
+	fn load_conditional_files(context: &Context) -> Result<Vec<ConditionalFilesConfig>> {
+		let conditional_files = context
+			.recipe
+			.config::<Vec<ConditionalFilesConfig>>("conditional_files")?
+			.unwrap_or_default();
+
+		Ok(conditional_files)
+	}
+
+	/// Removes the files whose condition doesn't hold, asking for any missing `when` values when
+	/// `prompt` is set. Files without a value are kept otherwise.
+	fn remove_conditional_files(
 		context: &Context,
 		replacements: &mut HashMap<String, String>,
+		prompt: bool,
 	) -> Result<()> {
-		let missing = find_missing_placeholders(context.project_dir, replacements)?;
-		for key in missing {
+		for conditional in Self::load_conditional_files(context)? {
+			let key = conditional.when;
+			if !replacements.contains_key(&key) {
+				if !prompt {
+					continue;
+				}
+
+				let include = Confirm::new(&format!("Enable {key}?"))
+					.with_help_message(&conditional.paths.join(", "))
+					.with_default(true)
+					.prompt()
+					.with_context(|| format!("Failed to prompt for {key}"))?;
+
+				replacements.insert(key.clone(), include.to_string());
+			}
+
+			if is_truthy(&replacements[&key]) {
+				continue;
+			}
+
+			let mut builder = GitignoreBuilder::new(context.project_dir);
+			for pattern in &conditional.paths {
+				builder
+					.add_line(None, pattern)
+					.with_context(|| format!("Invalid conditional file pattern {pattern}"))?;
+			}
+			let paths = builder
+				.build()
+				.context("Failed to build conditional file patterns")?;
+
+			template_files::remove_ignored(context.project_dir, context.project_dir, &paths)?;
+		}
+
+		Ok(())
+	}
+
+	/// Prompts for every key in `found` without a value, returning the ones left empty.
+	fn prompt_for_missing_placeholders(
+		found: HashSet<String>,
+		replacements: &mut HashMap<String, String>,
+	) -> Result<Vec<String>> {
+		let mut declined = Vec::new();
+
+		for key in find_missing_placeholders(found, replacements) {
 			let prompt = format!("What should {key} be replaced with?");
 			let value = Text::new(&prompt)
 				.prompt()
 				.with_context(|| format!("Failed to prompt for {key}"))?;
 
-			if !value.trim().is_ascii() {
+			if value.trim().is_empty() {
+				declined.push(key);
+			} else {
 				replacements.insert(key, value);
 			}
 		}
 
-		Ok(())
+		Ok(declined)
 	}
 }
 
-static PLACEHOLDER_RE: LazyLock<Regex> =
-	LazyLock::new(|| Regex::new(r"\{\{ *([^{}]*?) *\}\}").expect("valid regex"));
-
 fn find_missing_placeholders(
-	root: &Path,
+	found: HashSet<String>,
 	replacements: &HashMap<String, String>,
-) -> Result<Vec<String>> {
-	let found = collect_placeholders(root)?;
-
+) -> Vec<String> {
 	let mut missing: Vec<_> = found
 		.into_iter()
 		.filter(|key| !replacements.contains_key(key))
@@ -84,74 +392,18 @@ fn find_missing_placeholders(
 
 	missing.sort();
 
-	Ok(missing)
-}
-
-fn collect_placeholders(root: &Path) -> Result<HashSet<String>> {
-	let entries = walk_entries(root)?;
-	let mut keys = HashSet::new();
-
-	for entry in entries {
-		let path = entry.path();
-		if path != root
-			&& let Some(name) = path.file_name().and_then(|name| name.to_str())
-		{
-			extract_placeholders(name, &mut keys);
-		}
-
-		if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-			continue;
-		}
-
-		let contents =
-			fs::read(path).with_context(|| format!("Failed to read file {}", path.display()))?;
-
-		if contents.contains(&0) {
-			continue;
-		}
-
-		let Ok(text) = str::from_utf8(&contents) else {
-			continue;
-		};
-
-		extract_placeholders(text, &mut keys);
-	}
-
-	Ok(keys)
-}
-
-fn extract_placeholders(input: &str, keys: &mut HashSet<String>) {
-	for caps in PLACEHOLDER_RE.captures_iter(input) {
-		if let Some(matched) = caps.get(1) {
-			let trimmed = matched.as_str().trim();
-			if !trimmed.is_empty() {
-				keys.insert(trimmed.to_string());
-			}
-		}
-	}
-}
-
-fn walk_entries(root: &Path) -> Result<Vec<ignore::DirEntry>> {
-	WalkBuilder::new(root)
-		.hidden(false)
-		.git_ignore(false)
-		.git_exclude(false)
-		.git_global(false)
-		.filter_entry(|entry| entry.file_name() != OsStr::new(".git"))
-		.build()
-		.map(|entry| entry.context("Failed to read directory entry"))
-		.collect()
+	missing
 }
 
 #[cfg(test)]
 mod tests {
-	use super::extract_placeholders;
+	use super::syntax::Syntax;
 	use std::collections::HashSet;
 
 	#[test]
 	fn extract_placeholders_trims_and_collects() {
 		let mut keys = HashSet::new();
-		extract_placeholders("Hello {{NAME}} and {{ APP_ID }}!", &mut keys);
+		Syntax::default().extract("Hello {{NAME}} and {{ APP_ID }}!", &mut keys);
 
 		assert!(keys.contains("NAME"));
 		assert!(keys.contains("APP_ID"));
@@ -161,16 +413,40 @@ mod tests {
 	#[test]
 	fn extract_placeholders_ignores_empty() {
 		let mut keys = HashSet::new();
-		extract_placeholders("{{}} {{   }}", &mut keys);
+		Syntax::default().extract("{{}} {{   }}", &mut keys);
 		assert!(keys.is_empty());
 	}
 
 	#[test]
 	fn extract_placeholders_ignores_nested_braces() {
 		let mut keys = HashSet::new();
-		extract_placeholders("{{OUTER {{INNER}} OUTER}}", &mut keys);
+		Syntax::default().extract("{{OUTER {{INNER}} OUTER}}", &mut keys);
 
 		assert!(keys.contains("INNER"));
 		assert_eq!(keys.len(), 1);
 	}
+
+	#[test]
+	fn extract_placeholders_skips_escaped() {
+		let mut keys = HashSet::new();
+		Syntax::default().extract("\\{{ secrets.TOKEN }} {{NAME}}", &mut keys);
+
+		assert_eq!(keys, HashSet::from(["NAME".to_string()]));
+	}
+
+	#[test]
+	fn extract_placeholders_with_custom_delimiters() -> anyhow::Result<()> {
+		let mut keys = HashSet::new();
+		Syntax::new("[[", "]]")?.extract(
+			"name: [[NAME]]\ntoken: ${{ secrets.GITHUB_TOKEN }}",
+			&mut keys,
+		);
+		assert_eq!(keys, HashSet::from(["NAME".to_string()]));
+
+		let mut keys = HashSet::new();
+		Syntax::new("__", "__")?.extract("let id = \"__APP_ID__\";", &mut keys);
+		assert_eq!(keys, HashSet::from(["APP_ID".to_string()]));
+
+		Ok(())
+	}
 }
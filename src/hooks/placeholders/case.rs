@@ -0,0 +1,118 @@
+use heck::{
+	ToKebabCase, ToLowerCamelCase, ToShoutyKebabCase, ToShoutySnakeCase, ToSnakeCase, ToTitleCase,
+	ToUpperCamelCase,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Case {
+	Pascal,
+	Camel,
+	Snake,
+	ScreamingSnake,
+	Kebab,
+	ScreamingKebab,
+	Title,
+	Lower,
+	Upper,
+	Flat,
+	UpperFlat,
+}
+
+impl Case {
+	/// Variants a literal is recognized in when renaming, from most to least specific.
+	pub const VARIANTS: &[Self] = &[
+		Self::Pascal,
+		Self::Camel,
+		Self::Snake,
+		Self::ScreamingSnake,
+		Self::Kebab,
+		Self::ScreamingKebab,
+		Self::Title,
+		Self::Flat,
+		Self::UpperFlat,
+	];
+
+	/// The variants `literal` is renamed in besides itself. A single word like `Template` is only
+	/// renamed as written, since its lower and upper variants would also hit unrelated names like
+	/// `templates` or `TEMPLATE_DIR`.
+	pub fn rename_variants(literal: &str) -> &'static [Self] {
+		let words = literal
+			.to_snake_case()
+			.split('_')
+			.filter(|word| !word.is_empty())
+			.count();
+
+		if words > 1 { Self::VARIANTS } else { &[] }
+	}
+
+	pub fn from_filter(name: &str) -> Option<Self> {
+		match name {
+			"pascal_case" => Some(Self::Pascal),
+			"camel_case" => Some(Self::Camel),
+			"snake_case" => Some(Self::Snake),
+			"screaming_snake_case" => Some(Self::ScreamingSnake),
+			"kebab_case" => Some(Self::Kebab),
+			"screaming_kebab_case" => Some(Self::ScreamingKebab),
+			"title_case" => Some(Self::Title),
+			"lower" => Some(Self::Lower),
+			"upper" => Some(Self::Upper),
+			"flat_case" => Some(Self::Flat),
+			"upper_flat_case" => Some(Self::UpperFlat),
+			_ => None,
+		}
+	}
+
+	pub fn apply(self, input: &str) -> String {
+		match self {
+			Self::Pascal => input.to_upper_camel_case(),
+			Self::Camel => input.to_lower_camel_case(),
+			Self::Snake => input.to_snake_case(),
+			Self::ScreamingSnake => input.to_shouty_snake_case(),
+			Self::Kebab => input.to_kebab_case(),
+			Self::ScreamingKebab => input.to_shouty_kebab_case(),
+			Self::Title => input.to_title_case(),
+			Self::Lower => input.to_lowercase(),
+			Self::Upper => input.to_uppercase(),
+			Self::Flat => input.to_snake_case().replace('_', ""),
+			Self::UpperFlat => input.to_shouty_snake_case().replace('_', ""),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Case;
+
+	#[test]
+	fn only_renames_variants_of_several_words() {
+		assert_eq!(Case::rename_variants("TemplateApp"), Case::VARIANTS);
+		assert_eq!(
+			Case::rename_variants("com.example.template"),
+			Case::VARIANTS
+		);
+		assert!(Case::rename_variants("Template").is_empty());
+	}
+
+	#[test]
+	fn applies_every_variant() {
+		let variants: Vec<_> = Case::VARIANTS
+			.iter()
+			.map(|case| case.apply("TemplateApp"))
+			.collect();
+
+		assert_eq!(
+			variants,
+			[
+				"TemplateApp",
+				"templateApp",
+				"template_app",
+				"TEMPLATE_APP",
+				"template-app",
+				"TEMPLATE-APP",
+				"Template App",
+				"templateapp",
+				"TEMPLATEAPP",
+			]
+		);
+	}
+}
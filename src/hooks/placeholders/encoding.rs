@@ -0,0 +1,216 @@
+use crate::hooks::placeholders::report::Skip;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::{borrow::Cow, collections::BTreeMap, fmt, fs::File, io::Read, path::Path};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Encoding {
+	#[serde(rename = "utf-8", alias = "utf8")]
+	Utf8,
+	#[serde(rename = "utf-16le", alias = "utf16le")]
+	Utf16Le,
+	#[serde(rename = "utf-16be", alias = "utf16be")]
+	Utf16Be,
+	#[serde(rename = "latin1", alias = "iso-8859-1")]
+	Latin1,
+}
+
+impl fmt::Display for Encoding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Utf8 => write!(f, "UTF-8"),
+			Self::Utf16Le => write!(f, "UTF-16LE"),
+			Self::Utf16Be => write!(f, "UTF-16BE"),
+			Self::Latin1 => write!(f, "Latin-1"),
+		}
+	}
+}
+
+/// Per-glob encodings configured under `[recipe.placeholders.encodings]`.
+#[derive(Default)]
+pub struct Encodings {
+	globs: GlobSet,
+	encodings: Vec<Encoding>,
+}
+
+impl Encodings {
+	pub fn new(config: &BTreeMap<String, Encoding>) -> Result<Self> {
+		let mut builder = GlobSetBuilder::new();
+		for pattern in config.keys() {
+			builder.add(
+				Glob::new(pattern)
+					.with_context(|| format!("Invalid placeholder encoding glob {pattern}"))?,
+			);
+		}
+
+		Ok(Self {
+			globs: builder
+				.build()
+				.context("Failed to build placeholder encoding globs")?,
+			encodings: config.values().copied().collect(),
+		})
+	}
+
+	pub fn for_path(&self, relative: &Path) -> Option<Encoding> {
+		self.globs
+			.matches(relative)
+			.first()
+			.map(|index| self.encodings[*index])
+	}
+}
+
+/// A file's contents as UTF-8 text, remembering how to write them back.
+pub struct Decoded<'a> {
+	pub text: Cow<'a, str>,
+	encoding: Encoding,
+	bom: bool,
+}
+
+impl Decoded<'_> {
+	/// Whether the file is plain UTF-8, and can be handled line by line.
+	pub const fn is_plain(&self) -> bool {
+		matches!(self.encoding, Encoding::Utf8) && !self.bom
+	}
+
+	pub fn encode(&self, text: &str) -> Result<Vec<u8>> {
+		let mut output = Vec::with_capacity(text.len() + 3);
+
+		match self.encoding {
+			Encoding::Utf8 => {
+				if self.bom {
+					output.extend_from_slice(UTF8_BOM);
+				}
+				output.extend_from_slice(text.as_bytes());
+			},
+			Encoding::Utf16Le => {
+				if self.bom {
+					output.extend_from_slice(UTF16LE_BOM);
+				}
+				output.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+			},
+			Encoding::Utf16Be => {
+				if self.bom {
+					output.extend_from_slice(UTF16BE_BOM);
+				}
+				output.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+			},
+			Encoding::Latin1 => {
+				for char in text.chars() {
+					output.push(u8::try_from(u32::from(char)).map_err(|_| {
+						anyhow::anyhow!("`{char}` can't be written back as Latin-1")
+					})?);
+				}
+			},
+		}
+
+		Ok(output)
+	}
+}
+
+/// Decodes `contents`, using a BOM if there is one and the `declared` encoding otherwise.
+/// Files without either must be UTF-8 text.
+pub fn decode(contents: &[u8], declared: Option<Encoding>) -> Result<Decoded<'_>, Skip> {
+	let (encoding, bom, body) = if let Some(body) = contents.strip_prefix(UTF8_BOM) {
+		(Encoding::Utf8, true, body)
+	} else if let Some(body) = contents.strip_prefix(UTF16LE_BOM) {
+		(Encoding::Utf16Le, true, body)
+	} else if let Some(body) = contents.strip_prefix(UTF16BE_BOM) {
+		(Encoding::Utf16Be, true, body)
+	} else if let Some(encoding) = declared {
+		(encoding, false, contents)
+	} else {
+		if contents.contains(&0) {
+			return Err(Skip::Binary);
+		}
+
+		return str::from_utf8(contents)
+			.map(|text| Decoded {
+				bom: false,
+				text: Cow::Borrowed(text),
+				encoding: Encoding::Utf8,
+			})
+			.map_err(|_| Skip::NonUtf8);
+	};
+
+	let text = match encoding {
+		Encoding::Utf8 => str::from_utf8(body).ok().map(Cow::Borrowed),
+		Encoding::Utf16Le => decode_utf16(body, u16::from_le_bytes).map(Cow::Owned),
+		Encoding::Utf16Be => decode_utf16(body, u16::from_be_bytes).map(Cow::Owned),
+		Encoding::Latin1 => Some(Cow::Owned(body.iter().copied().map(char::from).collect())),
+	}
+	.ok_or(Skip::Undecodable(encoding))?;
+
+	Ok(Decoded {
+		text,
+		encoding,
+		bom,
+	})
+}
+
+fn decode_utf16(body: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+	if !body.len().is_multiple_of(2) {
+		return None;
+	}
+
+	String::from_utf16(
+		&body
+			.chunks_exact(2)
+			.map(|pair| from_bytes([pair[0], pair[1]]))
+			.collect::<Vec<_>>(),
+	)
+	.ok()
+}
+
+/// Whether the file at `path` starts with a UTF-16 BOM, without reading all of it.
+pub fn has_utf16_bom(path: &Path) -> Result<bool> {
+	let mut prefix = [0; 2];
+	let read = File::open(path)
+		.and_then(|mut file| file.read(&mut prefix))
+		.with_context(|| format!("Failed to read file {}", path.display()))?;
+
+	Ok(read == 2 && (prefix == UTF16LE_BOM || prefix == UTF16BE_BOM))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Encoding, decode};
+	use crate::hooks::placeholders::report::Skip;
+
+	#[test]
+	fn round_trips_utf16_with_bom() -> anyhow::Result<()> {
+		let mut contents = vec![0xFF, 0xFE];
+		contents.extend("Hello {{NAME}}".encode_utf16().flat_map(u16::to_le_bytes));
+
+		let decoded = decode(&contents, None).map_err(|skip| anyhow::anyhow!("{skip}"))?;
+		assert_eq!(decoded.text, "Hello {{NAME}}");
+		assert!(!decoded.is_plain());
+
+		let encoded = decoded.encode("Hello World")?;
+		assert_eq!(&encoded[..2], [0xFF, 0xFE]);
+		assert_eq!(
+			decode(&encoded, None).map(|d| d.text.into_owned()),
+			Ok("Hello World".to_string())
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn uses_declared_encoding_without_bom() -> anyhow::Result<()> {
+		let contents = b"caf\xe9 {{NAME}}";
+		assert_eq!(decode(contents, None).err(), Some(Skip::NonUtf8));
+
+		let decoded =
+			decode(contents, Some(Encoding::Latin1)).map_err(|skip| anyhow::anyhow!("{skip}"))?;
+		assert_eq!(decoded.text, "café {{NAME}}");
+		assert_eq!(decoded.encode("café MyProject")?, b"caf\xe9 MyProject");
+		assert!(decoded.encode("✓").is_err());
+
+		Ok(())
+	}
+}
@@ -1,156 +1,527 @@
+use crate::hooks::placeholders::{
+	case::Case,
+	encoding::{self, Encodings},
+	report::Report,
+	scan::{STREAMING_THRESHOLD, Scan},
+	syntax::Syntax,
+};
 use anyhow::{Context, Result};
-use grep_matcher::Matcher;
-use grep_regex::RegexMatcherBuilder;
-use ignore::WalkBuilder;
+use globset::{Glob, GlobMatcher};
+use grep_matcher::{Captures, Matcher};
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
 use regex::escape as regex_escape;
 use std::{
 	cmp::Reverse,
 	collections::HashMap,
-	ffi::OsStr,
-	fs,
+	fs::{self, File},
+	io::{BufRead, BufReader, BufWriter, Write},
+	iter,
 	path::{Path, PathBuf},
+	str,
+	sync::{
+		Mutex,
+		atomic::{AtomicUsize, Ordering},
+	},
+	thread,
 };
 
+/// How many times each key, literal or pattern was replaced.
+type Counts<'a> = HashMap<&'a str, usize>;
+
 pub struct Replacer {
-	matcher: grep_regex::RegexMatcher,
-	replacements: HashMap<Vec<u8>, Vec<u8>>,
+	nested_paths: bool,
+	encodings: Encodings,
+	syntax: Syntax,
+	matcher: RegexMatcher,
+	/// Placeholder values by key.
+	values: HashMap<String, Replacement>,
+	/// Rename values by the literal they replace.
+	literals: HashMap<Vec<u8>, Replacement>,
+	regex_replacements: Vec<RegexReplacement>,
 }
 
-impl Replacer {
-	pub fn new(replacements: HashMap<String, String>) -> Result<Self> {
-		let mut literals = Vec::with_capacity(replacements.len());
-		let mut replacement_map = HashMap::with_capacity(replacements.len());
-
-		for (key, value) in replacements {
-			let placeholder = format!("{{{{{key}}}}}");
-			literals.push(placeholder.clone());
-			replacement_map.insert(placeholder.into_bytes(), value.into_bytes());
+struct Replacement {
+	value: Vec<u8>,
+	/// What the report counts this replacement as.
+	label: String,
+}
+
+/// A `[[recipe.regex_replacements]]` entry, applied to the contents of matching files.
+pub struct RegexReplacement {
+	matcher: RegexMatcher,
+	label: String,
+	template: String,
+	replacement: Vec<u8>,
+	files: Option<GlobMatcher>,
+}
+
+impl RegexReplacement {
+	/// `replacement` may reference capture groups as `$1` or `${name}`, and placeholders that get
+	/// rendered once their values are known.
+	pub fn new(pattern: &str, replacement: String, files: Option<&str>) -> Result<Self> {
+		let matcher = RegexMatcherBuilder::new()
+			.build(pattern)
+			.with_context(|| format!("Invalid regex replacement pattern {pattern}"))?;
+
+		let files = files
+			.map(|glob| {
+				Glob::new(glob)
+					.map(|glob| glob.compile_matcher())
+					.with_context(|| format!("Invalid regex replacement files glob {glob}"))
+			})
+			.transpose()?;
+
+		Ok(Self {
+			files,
+			matcher,
+			label: format!("/{pattern}/"),
+			template: replacement,
+			replacement: Vec::new(),
+		})
+	}
+
+	pub fn template(&self) -> &str {
+		&self.template
+	}
+
+	pub fn applies_to(&self, relative: &Path) -> bool {
+		self.files
+			.as_ref()
+			.is_none_or(|files| files.is_match(relative))
+	}
+
+	pub fn is_match(&self, input: &[u8]) -> bool {
+		self.matcher.is_match(input).unwrap_or(true)
+	}
+
+	fn replace_bytes<'a>(
+		&'a self,
+		input: &[u8],
+		counts: &mut Counts<'a>,
+	) -> Result<Option<Vec<u8>>> {
+		let mut captures = self.matcher.new_captures()?;
+		let mut output = Vec::with_capacity(input.len());
+		let mut did_replace = false;
+
+		self.matcher.replace_with_captures(
+			input,
+			&mut captures,
+			&mut output,
+			|captures, dst| {
+				captures.interpolate(
+					|name| self.matcher.capture_index(name),
+					input,
+					&self.replacement,
+					dst,
+				);
+				*counts.entry(&self.label).or_default() += 1;
+				did_replace = true;
+				true
+			},
+		)?;
+
+		if did_replace && output != input {
+			Ok(Some(output))
+		} else {
+			Ok(None)
 		}
+	}
+}
 
-		let pattern = literals
+impl Replacer {
+	/// Builds a replacer for `replacements` placeholders, and for `renames` literals (with their
+	/// already rendered values) in every case variant, for literals of more than one word.
+	pub fn new(
+		replacements: HashMap<String, String>,
+		renames: &[(String, String)],
+		mut regex_replacements: Vec<RegexReplacement>,
+		syntax: &Syntax,
+		encodings: Encodings,
+		nested_paths: bool,
+	) -> Result<Self> {
+		// Variables are substituted before the regex runs, so `$` in their values must stay literal.
+		let escaped = replacements
 			.iter()
-			.map(|literal| regex_escape(literal))
-			.collect::<Vec<_>>()
-			.join("|");
+			.map(|(key, value)| (key.clone(), value.replace('$', "$$")))
+			.collect();
+		for regex_replacement in &mut regex_replacements {
+			regex_replacement.replacement = syntax
+				.render(&regex_replacement.template, &escaped)?
+				.into_bytes();
+		}
+
+		let values = replacements
+			.into_iter()
+			.map(|(key, value)| {
+				let replacement = Replacement {
+					value: value.into_bytes(),
+					label: key.clone(),
+				};
+
+				(key, replacement)
+			})
+			.collect();
+
+		let mut literal_map = HashMap::new();
+		for (literal, value) in renames {
+			let variants = iter::once((literal.clone(), value.clone())).chain(
+				Case::rename_variants(literal)
+					.iter()
+					.map(|case| (case.apply(literal), case.apply(value))),
+			);
+
+			for (variant, value) in variants {
+				if !variant.is_empty() {
+					literal_map
+						.entry(variant.into_bytes())
+						.or_insert_with(|| Replacement {
+							value: value.into_bytes(),
+							label: literal.clone(),
+						});
+				}
+			}
+		}
+
+		// Longer literals go first, so a rename like `TemplateApp` wins over `Template`.
+		let mut literals = literal_map
+			.keys()
+			.map(|literal| String::from_utf8_lossy(literal).into_owned())
+			.collect::<Vec<_>>();
+		literals.sort_by(|left, right| right.len().cmp(&left.len()).then(left.cmp(right)));
+
+		// Placeholders are matched like rendering does, so `{{ KEY }}` is replaced like `{{KEY}}`.
+		let pattern = [
+			syntax.escaped_pattern(),
+			syntax.placeholder_pattern().to_string(),
+		]
+		.into_iter()
+		.chain(literals.iter().map(|literal| regex_escape(literal)))
+		.collect::<Vec<_>>()
+		.join("|");
 		let matcher = RegexMatcherBuilder::new()
 			.build(&pattern)
 			.context("Failed to build placeholder matcher")?;
 
 		Ok(Self {
 			matcher,
-			replacements: replacement_map,
+			encodings,
+			nested_paths,
+			values,
+			regex_replacements,
+			syntax: syntax.clone(),
+			literals: literal_map,
 		})
 	}
 
-	pub fn apply(&self, root: &Path) -> Result<()> {
-		self.rename_directories(root)?;
-		self.rename_files(root)?;
-		self.replace_file_contents(root)?;
-		Ok(())
-	}
+	/// Rewrites the files the scan flagged, then renames files and directories. Contents go first
+	/// so every path the scan recorded is still valid.
+	pub fn apply(&self, root: &Path, scan: &Scan) -> Result<Report> {
+		let mut report = Report {
+			skipped: scan
+				.skipped
+				.iter()
+				.map(|(path, reason)| (relative(root, path), *reason))
+				.collect(),
+			..Report::default()
+		};
 
-	fn rename_directories(&self, root: &Path) -> Result<()> {
-		let entries = walk_entries(root)?;
-		let mut dirs: Vec<PathBuf> = entries
-			.iter()
-			.filter(|entry| entry.file_type().is_some_and(|ft| ft.is_dir()))
-			.map(|entry| entry.path().to_path_buf())
-			.collect();
+		self.replace_file_contents(root, &scan.rewrites, &mut report)?;
+		self.rename_files(root, &scan.files, &mut report)?;
+		self.rename_directories(root, &scan.dirs, &mut report)?;
 
+		Ok(report)
+	}
+
+	fn rename_directories(&self, root: &Path, dirs: &[PathBuf], report: &mut Report) -> Result<()> {
+		let mut dirs = dirs.iter().collect::<Vec<_>>();
 		dirs.sort_by_key(|dir| Reverse(dir.components().count()));
 
 		for dir in dirs {
-			if dir == root {
-				continue;
-			}
-
 			let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
 				continue;
 			};
 
-			let replaced = self.replace_text(name)?;
+			let mut counts = Counts::new();
+			let replaced = self.replace_text(name, &mut counts)?;
 			if replaced == name {
 				continue;
 			}
 
-			let Some(parent) = dir.parent() else {
-				continue;
-			};
-
-			fs::rename(&dir, parent.join(replaced))?;
+			let target = self.rename_target(root, dir, &replaced)?;
+			move_entry(dir, &target)?;
+			report.add_counts(counts);
+			report
+				.renamed
+				.push((relative(root, dir), relative(root, &target)));
 		}
 
 		Ok(())
 	}
 
-	fn rename_files(&self, root: &Path) -> Result<()> {
-		let entries = walk_entries(root)?;
-		for entry in entries {
-			if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-				continue;
-			}
-
-			let path = entry.path();
+	fn rename_files(&self, root: &Path, files: &[PathBuf], report: &mut Report) -> Result<()> {
+		for path in files {
 			let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
 				continue;
 			};
 
-			let replaced = self.replace_text(name)?;
+			let mut counts = Counts::new();
+			let replaced = self.replace_text(name, &mut counts)?;
 			if replaced == name {
 				continue;
 			}
 
-			let Some(parent) = path.parent() else {
-				continue;
-			};
-
-			fs::rename(path, parent.join(replaced))?;
+			let target = self.rename_target(root, path, &replaced)?;
+			move_entry(path, &target)?;
+			report.add_counts(counts);
+			report
+				.renamed
+				.push((relative(root, path), relative(root, &target)));
 		}
 
 		Ok(())
 	}
 
-	fn replace_file_contents(&self, root: &Path) -> Result<()> {
-		let entries = walk_entries(root)?;
-		for entry in entries {
-			if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-				continue;
+	/// Resolves where `path` moves to once its name renders to `replaced`, making sure the
+	/// rendered value can't point outside of `root`.
+	fn rename_target(&self, root: &Path, path: &Path, replaced: &str) -> Result<PathBuf> {
+		let parent = path
+			.parent()
+			.with_context(|| format!("Cannot rename {}", path.display()))?;
+		let segments = replaced.split('/').collect::<Vec<_>>();
+
+		if segments.len() > 1 && !self.nested_paths {
+			anyhow::bail!(
+				"Renaming {} to `{replaced}` would create nested directories. Set `nested_paths = true` under [recipe.placeholders] to allow it",
+				path.display()
+			);
+		}
+
+		for segment in &segments {
+			if segment.is_empty()
+				|| *segment == "."
+				|| *segment == ".."
+				|| segment.contains(['\\', '\0'])
+			{
+				anyhow::bail!(
+					"Renaming {} to `{replaced}` is not allowed: `{segment}` is not a valid file name",
+					path.display()
+				);
+			}
+		}
+
+		let target = segments
+			.iter()
+			.fold(parent.to_path_buf(), |target, segment| target.join(segment));
+		if !target.starts_with(root) {
+			anyhow::bail!(
+				"Renaming {} to `{replaced}` would move it outside of the project",
+				path.display()
+			);
+		}
+
+		Ok(target)
+	}
+
+	/// Rewrites `paths` across all available cores.
+	fn replace_file_contents(
+		&self,
+		root: &Path,
+		paths: &[PathBuf],
+		report: &mut Report,
+	) -> Result<()> {
+		let next = AtomicUsize::new(0);
+		let error = Mutex::new(None);
+		let shared = Mutex::new(report);
+		let workers = thread::available_parallelism()
+			.map_or(1, usize::from)
+			.min(paths.len());
+
+		thread::scope(|scope| {
+			for _ in 0..workers {
+				scope.spawn(|| {
+					while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+						let mut counts = Counts::new();
+						match self.replace_file(root, path, &mut counts) {
+							Ok(false) => {},
+							Ok(true) => {
+								let mut report = shared.lock().expect("poisoned");
+								report.add_counts(counts);
+								report.rewritten.push(relative(root, path));
+							},
+							Err(err) => {
+								error.lock().expect("poisoned").get_or_insert(err);
+								return;
+							},
+						}
+					}
+				});
 			}
+		});
+
+		error.into_inner().expect("poisoned").map_or(Ok(()), Err)
+	}
+
+	/// Rewrites a single file, returning whether anything in it was replaced.
+	fn replace_file<'a>(
+		&'a self,
+		root: &Path,
+		path: &Path,
+		counts: &mut Counts<'a>,
+	) -> Result<bool> {
+		let relative = path.strip_prefix(root).unwrap_or(path);
+		let size = fs::metadata(path)
+			.with_context(|| format!("Failed to read metadata of {}", path.display()))?
+			.len();
+
+		let declared = self.encodings.for_path(relative);
+		if size > STREAMING_THRESHOLD && declared.is_none() && !encoding::has_utf16_bom(path)? {
+			return self.replace_file_streaming(relative, path, counts);
+		}
+
+		let contents =
+			fs::read(path).with_context(|| format!("Failed to read file {}", path.display()))?;
+		let Ok(decoded) = encoding::decode(&contents, declared) else {
+			return Ok(false);
+		};
 
-			let path = entry.path();
-			let contents = fs::read(path)
-				.with_context(|| format!("Failed to read file {}", path.display()))?;
+		let Some(replaced) = self
+			.replace_contents(relative, decoded.text.as_bytes(), counts)
+			.with_context(|| format!("Failed to replace contents of {}", path.display()))?
+		else {
+			return Ok(false);
+		};
+
+		let replaced = if decoded.is_plain() {
+			replaced
+		} else {
+			String::from_utf8(replaced)
+				.context("Failed to decode replacement result")
+				.and_then(|text| decoded.encode(&text))
+				.with_context(|| format!("Failed to encode {}", path.display()))?
+		};
+
+		fs::write(path, replaced)
+			.with_context(|| format!("Failed to write file {}", path.display()))?;
+
+		Ok(true)
+	}
 
-			if contents.contains(&0) || std::str::from_utf8(&contents).is_err() {
+	/// Rewrites a large file line by line into a sibling file, which then replaces the original.
+	/// Regex replacements can't match across lines in this mode.
+	fn replace_file_streaming<'a>(
+		&'a self,
+		relative: &Path,
+		path: &Path,
+		counts: &mut Counts<'a>,
+	) -> Result<bool> {
+		let temp_path = path.with_file_name(format!(
+			".{}.new-tmp",
+			path.file_name().unwrap_or_default().to_string_lossy()
+		));
+
+		let result = self
+			.stream_contents(relative, path, &temp_path, counts)
+			.with_context(|| format!("Failed to replace contents of {}", path.display()));
+
+		match result {
+			Ok(true) => fs::rename(&temp_path, path)
+				.map(|()| true)
+				.with_context(|| format!("Failed to write file {}", path.display())),
+			Ok(false) => fs::remove_file(&temp_path)
+				.map(|()| false)
+				.with_context(|| format!("Failed to remove file {}", temp_path.display())),
+			Err(error) => {
+				let _ = fs::remove_file(&temp_path);
+				Err(error)
+			},
+		}
+	}
+
+	fn stream_contents<'a>(
+		&'a self,
+		relative: &Path,
+		source: &Path,
+		target: &Path,
+		counts: &mut Counts<'a>,
+	) -> Result<bool> {
+		let mut reader = BufReader::new(File::open(source)?);
+		let mut writer = BufWriter::new(File::create(target)?);
+		let mut line = Vec::new();
+		let mut did_replace = false;
+
+		while reader.read_until(b'\n', &mut line)? > 0 {
+			if let Some(replaced) = self.replace_contents(relative, &line, counts)? {
+				writer.write_all(&replaced)?;
+				did_replace = true;
+			} else {
+				writer.write_all(&line)?;
+			}
+
+			line.clear();
+		}
+
+		writer.flush()?;
+		Ok(did_replace)
+	}
+
+	fn replace_contents<'a>(
+		&'a self,
+		relative: &Path,
+		contents: &[u8],
+		counts: &mut Counts<'a>,
+	) -> Result<Option<Vec<u8>>> {
+		let mut replaced = self.replace_bytes(contents, counts)?;
+
+		for regex_replacement in &self.regex_replacements {
+			if !regex_replacement.applies_to(relative) {
 				continue;
 			}
 
-			if let Some(replaced) = self.replace_bytes(&contents)? {
-				fs::write(path, replaced)
-					.with_context(|| format!("Failed to write file {}", path.display()))?;
+			let input = replaced.as_deref().unwrap_or(contents);
+			if let Some(output) = regex_replacement.replace_bytes(input, counts)? {
+				replaced = Some(output);
 			}
 		}
 
-		Ok(())
+		Ok(replaced)
 	}
 
-	fn replace_text(&self, input: &str) -> Result<String> {
-		let Some(replaced) = self.replace_bytes(input.as_bytes())? else {
+	fn replace_text<'a>(&'a self, input: &str, counts: &mut Counts<'a>) -> Result<String> {
+		let Some(replaced) = self.replace_bytes(input.as_bytes(), counts)? else {
 			return Ok(input.to_string());
 		};
 
 		String::from_utf8(replaced).context("Failed to decode replacement result")
 	}
 
-	fn replace_bytes(&self, input: &[u8]) -> Result<Option<Vec<u8>>> {
+	fn replace_bytes<'a>(
+		&'a self,
+		input: &[u8],
+		counts: &mut Counts<'a>,
+	) -> Result<Option<Vec<u8>>> {
 		let mut output = Vec::with_capacity(input.len());
 		let mut did_replace = false;
 		self.matcher.replace(input, &mut output, |matched, dst| {
 			let needle = &input[matched];
-			if let Some(replacement) = self.replacements.get(needle) {
-				dst.extend_from_slice(replacement);
+			let replacement = self.literals.get(needle).or_else(|| {
+				let key = str::from_utf8(needle)
+					.ok()
+					.and_then(|text| self.syntax.key(text))?;
+				self.values.get(key)
+			});
+
+			if let Some(replacement) = replacement {
+				dst.extend_from_slice(&replacement.value);
+				*counts.entry(&replacement.label).or_default() += 1;
+				did_replace = true;
+			} else if let Some(unescaped) = needle
+				.strip_prefix(b"\\")
+				.filter(|rest| rest.starts_with(self.syntax.open().as_bytes()))
+			{
+				dst.extend_from_slice(unescaped);
 				did_replace = true;
 			} else {
+				// Placeholders without a value are left for the report.
 				dst.extend_from_slice(needle);
 			}
 			true
@@ -164,14 +535,44 @@ impl Replacer {
 	}
 }
 
-fn walk_entries(root: &Path) -> Result<Vec<ignore::DirEntry>> {
-	WalkBuilder::new(root)
-		.hidden(false)
-		.git_ignore(false)
-		.git_exclude(false)
-		.git_global(false)
-		.filter_entry(|entry| entry.file_name() != OsStr::new(".git"))
-		.build()
-		.map(|entry| entry.context("Failed to read directory entry"))
-		.collect()
+fn relative(root: &Path, path: &Path) -> PathBuf {
+	path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+/// Moves `source` to `target`, merging directories that render to the same name and refusing to
+/// overwrite existing files.
+fn move_entry(source: &Path, target: &Path) -> Result<()> {
+	let exists = target.symlink_metadata().is_ok();
+	if exists && !same_file::is_same_file(source, target).unwrap_or(false) {
+		if !(source.is_dir() && target.is_dir()) {
+			anyhow::bail!(
+				"Cannot rename {} to {}: a file with that name already exists",
+				source.display(),
+				target.display()
+			);
+		}
+
+		for entry in fs::read_dir(source)
+			.with_context(|| format!("Failed to read directory {}", source.display()))?
+		{
+			let entry = entry.context("Failed to read directory entry")?;
+			move_entry(&entry.path(), &target.join(entry.file_name()))?;
+		}
+
+		return fs::remove_dir(source)
+			.with_context(|| format!("Failed to remove directory {}", source.display()));
+	}
+
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent)
+			.with_context(|| format!("Failed to create directory {}", parent.display()))?;
+	}
+
+	fs::rename(source, target).with_context(|| {
+		format!(
+			"Failed to rename {} to {}",
+			source.display(),
+			target.display()
+		)
+	})
 }
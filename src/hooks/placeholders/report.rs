@@ -0,0 +1,181 @@
+use crate::hooks::placeholders::encoding::Encoding;
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt,
+	path::{Path, PathBuf},
+};
+
+/// How many entries of a list get printed before the rest is summarized.
+const MAX_LISTED: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skip {
+	Binary,
+	NonUtf8,
+	Undecodable(Encoding),
+}
+
+impl fmt::Display for Skip {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Binary => write!(f, "binary"),
+			Self::NonUtf8 => write!(f, "not UTF-8"),
+			Self::Undecodable(encoding) => write!(f, "not valid {encoding}"),
+		}
+	}
+}
+
+/// What the placeholder engine did to the project, with paths relative to its root.
+#[derive(Debug, Default)]
+pub struct Report {
+	pub renamed: Vec<(PathBuf, PathBuf)>,
+	pub rewritten: Vec<PathBuf>,
+	pub counts: HashMap<String, usize>,
+	pub skipped: Vec<(PathBuf, Skip)>,
+	/// Placeholders without a value, and the (renamed) paths they were left in.
+	pub leftovers: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl Report {
+	pub fn add_counts(&mut self, counts: HashMap<&str, usize>) {
+		for (label, count) in counts {
+			*self.counts.entry(label.to_string()).or_default() += count;
+		}
+	}
+
+	/// Records `keys` found in `path` that didn't get a value, following the renames applied since.
+	pub fn add_leftovers(&mut self, path: &Path, keys: &HashSet<String>) {
+		let path = self.renamed_path(path);
+
+		for key in keys {
+			self.leftovers
+				.entry(key.clone())
+				.or_default()
+				.push(path.clone());
+		}
+	}
+
+	fn renamed_path(&self, path: &Path) -> PathBuf {
+		self.renamed
+			.iter()
+			.fold(path.to_path_buf(), |path, (from, to)| {
+				path.strip_prefix(from)
+					.map_or_else(|_| path.clone(), |rest| to.join(rest))
+			})
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(
+			f,
+			"📝 Renamed {} and rewrote {}",
+			plural(self.renamed.len(), "path"),
+			plural(self.rewritten.len(), "file")
+		)?;
+
+		let mut counts = self.counts.iter().collect::<Vec<_>>();
+		counts.sort();
+		for (label, count) in counts {
+			writeln!(f, "  - {label}: {}", plural(*count, "replacement"))?;
+		}
+
+		if !self.skipped.is_empty() {
+			writeln!(
+				f,
+				"⚠️  Skipped {} that can't be rewritten",
+				plural(self.skipped.len(), "file")
+			)?;
+			write_list(
+				f,
+				"",
+				self.skipped
+					.iter()
+					.map(|(path, reason)| format!("{} ({reason})", path.display())),
+			)?;
+		}
+
+		if !self.leftovers.is_empty() {
+			writeln!(f, "⚠️  Placeholders left without a value")?;
+			for (key, paths) in &self.leftovers {
+				writeln!(f, "  - {key}")?;
+				write_list(f, "  ", paths.iter().map(|path| path.display().to_string()))?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn write_list(
+	f: &mut fmt::Formatter<'_>,
+	indent: &str,
+	items: impl ExactSizeIterator<Item = String>,
+) -> fmt::Result {
+	let total = items.len();
+
+	for item in items.take(MAX_LISTED) {
+		writeln!(f, "{indent}  - {item}")?;
+	}
+
+	if total > MAX_LISTED {
+		writeln!(f, "{indent}  - … and {} more", total - MAX_LISTED)?;
+	}
+
+	Ok(())
+}
+
+fn plural(count: usize, noun: &str) -> String {
+	if count == 1 {
+		format!("{count} {noun}")
+	} else {
+		format!("{count} {noun}s")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Report;
+	use std::{
+		collections::{HashMap, HashSet},
+		path::PathBuf,
+	};
+
+	#[test]
+	fn prints_counts_and_leftovers() {
+		let mut report = Report::default();
+		report.rewritten.push(PathBuf::from("README.md"));
+		report.add_counts(HashMap::from([("APP_ID", 2)]));
+		report.add_leftovers(
+			&PathBuf::from("README.md"),
+			&HashSet::from(["TEAM_ID".to_string()]),
+		);
+
+		assert_eq!(
+			report.to_string(),
+			"📝 Renamed 0 paths and rewrote 1 file\n  - APP_ID: 2 replacements\n⚠️  Placeholders left without a value\n  - TEAM_ID\n    - README.md\n"
+		);
+	}
+
+	#[test]
+	fn leftovers_follow_renames() {
+		let mut report = Report::default();
+		report.renamed.push((
+			PathBuf::from("{{NAME}}/a.txt"),
+			PathBuf::from("{{NAME}}/b.txt"),
+		));
+		report
+			.renamed
+			.push((PathBuf::from("{{NAME}}"), PathBuf::from("MyProject")));
+
+		report.add_leftovers(
+			&PathBuf::from("{{NAME}}/a.txt"),
+			&HashSet::from(["TEAM_ID".to_string()]),
+		);
+
+		assert_eq!(
+			report.leftovers["TEAM_ID"],
+			[PathBuf::from("MyProject/b.txt")]
+		);
+	}
+}
@@ -0,0 +1,233 @@
+use crate::hooks::{
+	placeholders::{
+		case::Case,
+		encoding::{self, Encodings},
+		replacer::RegexReplacement,
+		report::Skip,
+		syntax::Syntax,
+	},
+	template_scripts::SCRIPTS_DIR,
+};
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use ignore::{WalkBuilder, WalkState};
+use regex::escape as regex_escape;
+use std::{
+	collections::HashSet,
+	ffi::OsStr,
+	fs::{self, File},
+	io::{BufRead, BufReader},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+/// Files larger than this are scanned and rewritten line by line instead of being read whole.
+pub const STREAMING_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Everything the replacer needs to know about the template, gathered in a single walk.
+#[derive(Default)]
+pub struct Scan {
+	pub keys: HashSet<String>,
+	pub files: Vec<PathBuf>,
+	pub dirs: Vec<PathBuf>,
+	/// Text files that contain something the replacer may rewrite.
+	pub rewrites: Vec<PathBuf>,
+	pub skipped: Vec<(PathBuf, Skip)>,
+	/// The placeholder keys each entry contains, in its name or contents.
+	pub placeholders: Vec<(PathBuf, HashSet<String>)>,
+}
+
+pub struct Scanner<'a> {
+	syntax: &'a Syntax,
+	exclude: &'a GlobSet,
+	encodings: &'a Encodings,
+	/// Paths marked `binary` or `-text` in the template's `.gitattributes`.
+	binary: &'a HashSet<PathBuf>,
+	literals: Option<RegexMatcher>,
+	regex_replacements: &'a [RegexReplacement],
+}
+
+impl<'a> Scanner<'a> {
+	pub fn new(
+		syntax: &'a Syntax,
+		exclude: &'a GlobSet,
+		encodings: &'a Encodings,
+		binary: &'a HashSet<PathBuf>,
+		renames: impl IntoIterator<Item = &'a String>,
+		regex_replacements: &'a [RegexReplacement],
+	) -> Result<Self> {
+		let literals = renames
+			.into_iter()
+			.flat_map(|literal| {
+				Case::VARIANTS
+					.iter()
+					.map(|case| case.apply(literal))
+					.chain([literal.clone()])
+			})
+			.filter(|literal| !literal.is_empty())
+			.map(|literal| regex_escape(&literal))
+			.collect::<Vec<_>>();
+
+		let literals = if literals.is_empty() {
+			None
+		} else {
+			Some(
+				RegexMatcherBuilder::new()
+					.build(&literals.join("|"))
+					.context("Failed to build rename matcher")?,
+			)
+		};
+
+		Ok(Self {
+			syntax,
+			exclude,
+			encodings,
+			binary,
+			literals,
+			regex_replacements,
+		})
+	}
+
+	/// Walks `root` in parallel, collecting placeholder keys from names and contents.
+	pub fn scan(&self, root: &Path) -> Result<Scan> {
+		let scan = Mutex::new(Scan::default());
+		let error = Mutex::new(None);
+
+		let scripts_dir = root.join(SCRIPTS_DIR);
+
+		WalkBuilder::new(root)
+			.hidden(false)
+			.git_ignore(false)
+			.git_exclude(false)
+			.git_global(false)
+			// The template's scripts run as they are, so they're never rendered.
+			.filter_entry(move |entry| {
+				entry.file_name() != OsStr::new(".git") && entry.path() != scripts_dir
+			})
+			.build_parallel()
+			.run(|| {
+				Box::new(|entry| {
+					match entry
+						.context("Failed to read directory entry")
+						.and_then(|entry| self.scan_entry(root, &entry, &scan))
+					{
+						Ok(()) => WalkState::Continue,
+						Err(err) => {
+							error.lock().expect("poisoned").get_or_insert(err);
+							WalkState::Quit
+						},
+					}
+				})
+			});
+
+		if let Some(error) = error.into_inner().expect("poisoned") {
+			return Err(error);
+		}
+
+		Ok(scan.into_inner().expect("poisoned"))
+	}
+
+	fn scan_entry(&self, root: &Path, entry: &ignore::DirEntry, scan: &Mutex<Scan>) -> Result<()> {
+		let path = entry.path();
+		if path == root {
+			return Ok(());
+		}
+
+		let mut keys = HashSet::new();
+		if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+			self.syntax.extract(name, &mut keys);
+		}
+
+		let file_type = entry.file_type();
+		let is_file = file_type.is_some_and(|ft| ft.is_file());
+		let relative = path.strip_prefix(root).unwrap_or(path);
+
+		let rewrite = if !is_file || self.exclude.is_match(relative) {
+			Ok(false)
+		} else if self.binary.contains(relative) {
+			Err(Skip::Binary)
+		} else {
+			let size = entry
+				.metadata()
+				.with_context(|| format!("Failed to read metadata of {}", path.display()))?
+				.len();
+
+			let declared = self.encodings.for_path(relative);
+			if size > STREAMING_THRESHOLD && declared.is_none() && !encoding::has_utf16_bom(path)? {
+				self.scan_stream(path, relative, &mut keys)?
+			} else {
+				let contents = fs::read(path)
+					.with_context(|| format!("Failed to read file {}", path.display()))?;
+				encoding::decode(&contents, declared)
+					.map(|decoded| self.scan_text(relative, &decoded.text, &mut keys))
+			}
+		};
+
+		let mut scan = scan.lock().expect("poisoned");
+		scan.keys.extend(keys.iter().cloned());
+		if is_file {
+			scan.files.push(path.to_path_buf());
+		} else if file_type.is_some_and(|ft| ft.is_dir()) {
+			scan.dirs.push(path.to_path_buf());
+		}
+		match rewrite {
+			Ok(true) => scan.rewrites.push(path.to_path_buf()),
+			Ok(false) => {},
+			Err(reason) => scan.skipped.push((path.to_path_buf(), reason)),
+		}
+		if !keys.is_empty() {
+			scan.placeholders.push((path.to_path_buf(), keys));
+		}
+		drop(scan);
+
+		Ok(())
+	}
+
+	/// Scans a large file line by line, returning whether it may need rewriting or why it's skipped.
+	fn scan_stream(
+		&self,
+		path: &Path,
+		relative: &Path,
+		keys: &mut HashSet<String>,
+	) -> Result<Result<bool, Skip>> {
+		let file =
+			File::open(path).with_context(|| format!("Failed to read file {}", path.display()))?;
+		let mut reader = BufReader::new(file);
+		let mut line = Vec::new();
+		let mut line_keys = HashSet::new();
+		let mut rewrite = false;
+
+		while reader
+			.read_until(b'\n', &mut line)
+			.with_context(|| format!("Failed to read file {}", path.display()))?
+			> 0
+		{
+			match encoding::decode(&line, None) {
+				Ok(decoded) => rewrite |= self.scan_text(relative, &decoded.text, &mut line_keys),
+				Err(reason) => return Ok(Err(reason)),
+			}
+
+			line.clear();
+		}
+
+		keys.extend(line_keys);
+		Ok(Ok(rewrite))
+	}
+
+	/// Collects the keys in `text`, returning whether the file may need rewriting.
+	fn scan_text(&self, relative: &Path, text: &str, keys: &mut HashSet<String>) -> bool {
+		let contents = text.as_bytes();
+		self.syntax.extract(text, keys);
+
+		text.contains(self.syntax.open())
+			|| self
+				.literals
+				.as_ref()
+				.is_some_and(|literals| literals.is_match(contents).unwrap_or(true))
+			|| self.regex_replacements.iter().any(|regex_replacement| {
+				regex_replacement.applies_to(relative) && regex_replacement.is_match(contents)
+			})
+	}
+}
@@ -0,0 +1,254 @@
+use crate::hooks::placeholders::case::Case;
+use anyhow::{Context, Result};
+use regex::{Captures, Regex, escape as regex_escape};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_OPEN: &str = "{{";
+const DEFAULT_CLOSE: &str = "}}";
+
+/// Delimiters configured through `delimiters = ["[[", "]]"]` under `[recipe.placeholders]`.
+#[derive(Debug, Deserialize)]
+pub struct Delimiters(String, String);
+
+#[derive(Clone)]
+pub struct Syntax {
+	open: String,
+	regex: Regex,
+	/// Matches a whole placeholder, like `{{ KEY }}`, without capturing anything.
+	placeholder: String,
+}
+
+impl Syntax {
+	pub fn new(open: &str, close: &str) -> Result<Self> {
+		if open.is_empty() || close.is_empty() {
+			anyhow::bail!("Placeholder delimiters cannot be empty");
+		}
+
+		// Keys can't contain the delimiter punctuation, so `{{A {{B}} C}}` only matches `B`.
+		let excluded = open
+			.chars()
+			.chain(close.chars())
+			.filter(|char| !char.is_alphanumeric() && *char != '_')
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.map(|char| regex_escape(&char.to_string()))
+			.collect::<String>();
+		// With the same delimiter on both sides, like `__KEY__`, any identifier would match, so
+		// Python's `__init__` isn't taken for a placeholder: keys must be uppercase there.
+		let key = if open == close {
+			r"[A-Z][A-Z0-9_]*?(?: *\| *[a-z_]+)*?".to_string()
+		} else if excluded.is_empty() {
+			".*?".to_string()
+		} else {
+			format!("[^{excluded}]*?")
+		};
+
+		let (open_pattern, close_pattern) = (regex_escape(open), regex_escape(close));
+
+		// A leading backslash escapes the placeholder, so `\{{KEY}}` renders as a literal `{{KEY}}`.
+		let pattern = format!(r"(\\)?{open_pattern} *({key}) *{close_pattern}");
+		let regex = Regex::new(&pattern)
+			.with_context(|| format!("Invalid placeholder delimiters {open} and {close}"))?;
+
+		Ok(Self {
+			regex,
+			placeholder: format!(r"{open_pattern} *{key} *{close_pattern}"),
+			open: open.to_string(),
+		})
+	}
+
+	pub fn from_config(delimiters: Option<&Delimiters>) -> Result<Self> {
+		match delimiters {
+			Some(Delimiters(open, close)) => Self::new(open, close),
+			None => Ok(Self::default()),
+		}
+	}
+
+	pub fn open(&self) -> &str {
+		&self.open
+	}
+
+	/// A pattern matching placeholders like `{{KEY}}` or `{{ KEY }}`.
+	pub fn placeholder_pattern(&self) -> &str {
+		&self.placeholder
+	}
+
+	/// A pattern matching escaped placeholders like `\{{KEY}}`, which render without the backslash.
+	pub fn escaped_pattern(&self) -> String {
+		format!(r"\\{}", self.placeholder)
+	}
+
+	/// The key of `placeholder` when it's a whole, unescaped placeholder like `{{ KEY }}`.
+	pub fn key<'a>(&self, placeholder: &'a str) -> Option<&'a str> {
+		let caps = self.regex.captures(placeholder)?;
+		if caps.get(1).is_some() || caps.get(0)?.range() != (0..placeholder.len()) {
+			return None;
+		}
+
+		Some(caps.get(2)?.as_str().trim()).filter(|key| !key.is_empty())
+	}
+
+	pub fn extract(&self, input: &str, keys: &mut HashSet<String>) {
+		for caps in self.regex.captures_iter(input) {
+			if caps.get(1).is_some() {
+				continue;
+			}
+
+			if let Some(matched) = caps.get(2) {
+				let trimmed = matched.as_str().trim();
+				if !trimmed.is_empty() {
+					keys.insert(trimmed.to_string());
+				}
+			}
+		}
+	}
+
+	/// Keys referenced by a template value, ignoring any `| filter` suffixes.
+	pub fn template_keys(&self, template: &str, keys: &mut HashSet<String>) {
+		let mut expressions = HashSet::new();
+		self.extract(template, &mut expressions);
+
+		keys.extend(
+			expressions
+				.iter()
+				.map(|expression| parse_expression(expression).0.to_string()),
+		);
+	}
+
+	/// Renders a template value like `{{NAME | pascal_case}}` with the given variables.
+	pub fn render(&self, template: &str, variables: &HashMap<String, String>) -> Result<String> {
+		self.render_with(template, |expression| {
+			render_expression(expression, variables).map(Some)
+		})
+	}
+
+	/// Like [`Self::render`], but leaves placeholders for unknown keys as written, like the
+	/// `{{.Id}}` of a Go template, and passes every value through `escape`, which may refuse it.
+	pub fn render_known(
+		&self,
+		template: &str,
+		variables: &HashMap<String, String>,
+		escape: impl Fn(&str) -> Result<String>,
+	) -> Result<String> {
+		self.render_with(template, |expression| {
+			if !variables.contains_key(parse_expression(expression).0) {
+				return Ok(None);
+			}
+
+			render_expression(expression, variables)
+				.and_then(|value| escape(&value))
+				.map(Some)
+		})
+	}
+
+	/// Replaces every unescaped placeholder with what `render` returns for its expression, keeping
+	/// it as written when that's `None`.
+	fn render_with(
+		&self,
+		template: &str,
+		mut render: impl FnMut(&str) -> Result<Option<String>>,
+	) -> Result<String> {
+		let mut error = None;
+		let rendered = self.regex.replace_all(template, |caps: &Captures| {
+			let expression = caps.get(2).map_or("", |matched| matched.as_str().trim());
+			if caps.get(1).is_some() || expression.is_empty() {
+				return caps[0].trim_start_matches('\\').to_string();
+			}
+
+			match render(expression) {
+				Ok(Some(value)) => value,
+				Ok(None) => caps[0].to_string(),
+				Err(err) => {
+					error.get_or_insert(err);
+					String::new()
+				},
+			}
+		});
+
+		if let Some(error) = error {
+			return Err(error.context(format!("Failed to render `{template}`")));
+		}
+
+		Ok(rendered.into_owned())
+	}
+}
+
+fn parse_expression(expression: &str) -> (&str, impl Iterator<Item = &str>) {
+	let mut parts = expression.split('|').map(str::trim);
+	let key = parts.next().unwrap_or_default();
+
+	(key, parts)
+}
+
+fn render_expression(expression: &str, variables: &HashMap<String, String>) -> Result<String> {
+	let (key, filters) = parse_expression(expression);
+	let mut value = variables
+		.get(key)
+		.with_context(|| format!("Unknown variable {key}"))?
+		.clone();
+
+	for filter in filters {
+		let case = Case::from_filter(filter).with_context(|| format!("Unknown filter {filter}"))?;
+		value = case.apply(&value);
+	}
+
+	Ok(value)
+}
+
+impl Default for Syntax {
+	fn default() -> Self {
+		Self::new(DEFAULT_OPEN, DEFAULT_CLOSE).expect("valid default delimiters")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Syntax;
+	use std::collections::{HashMap, HashSet};
+
+	#[test]
+	fn symmetric_delimiters_only_match_uppercase_keys() -> anyhow::Result<()> {
+		let syntax = Syntax::new("__", "__")?;
+		let mut keys = HashSet::new();
+		syntax.extract(
+			"class __NAME__:\n    def __init__(self): __name__ == \"__main__\" and __APP_ID | snake_case__",
+			&mut keys,
+		);
+
+		assert_eq!(
+			keys,
+			HashSet::from(["NAME".to_string(), "APP_ID | snake_case".to_string()])
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn render_applies_filters() -> anyhow::Result<()> {
+		let variables = HashMap::from([("NAME".to_string(), "my project".to_string())]);
+		let syntax = Syntax::default();
+
+		assert_eq!(
+			syntax.render("{{NAME | pascal_case}}App", &variables)?,
+			"MyProjectApp"
+		);
+		assert_eq!(
+			syntax.render("com.example.{{ NAME|snake_case|upper }}", &variables)?,
+			"com.example.MY_PROJECT"
+		);
+		assert!(syntax.render("{{MISSING}}", &variables).is_err());
+		assert!(syntax.render("{{NAME | reversed}}", &variables).is_err());
+
+		assert_eq!(
+			syntax.render_known(
+				"{{.Id}} {{ NAME }} {{MISSING | upper}}",
+				&variables,
+				|value| Ok(format!("'{value}'"))
+			)?,
+			"{{.Id}} 'my project' {{MISSING | upper}}"
+		);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,357 @@
+use crate::{
+	hooks::{Context, Hook, Stage, is_inside},
+	recipes,
+};
+use anyhow::{Context as AnyhowContext, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, HashMap},
+	env,
+	ffi::OsStr,
+	fs,
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+/// Plugins found on `PATH` are named `new-hook-<name>`.
+const PLUGIN_PREFIX: &str = "new-hook-";
+/// The global configuration, next to the recipes.
+const GLOBAL_CONFIG: &str = "config.toml";
+/// Where plugins run unless they're configured otherwise.
+const DEFAULT_STAGES: &[Stage] = &[Stage::Finalize];
+/// Bumped whenever the request or response changes incompatibly.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// An external executable that runs at the stages it's enabled for, reading a JSON description
+/// of the context on stdin and answering with variable updates and file operations on stdout.
+pub struct Plugin {
+	name: String,
+	program: PathBuf,
+	stages: Vec<Stage>,
+}
+
+/// `[plugins.<name>]` in the global configuration, or `[recipe.plugins.<name>]` in a recipe.
+#[derive(Debug, Default, Deserialize)]
+struct PluginConfig {
+	/// Defaults to `new-hook-<name>` on `PATH`.
+	#[serde(default)]
+	path: Option<PathBuf>,
+	/// Defaults to `finalize`. An empty list disables the plugin.
+	#[serde(default)]
+	stages: Option<Vec<Stage>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GlobalConfig {
+	#[serde(default)]
+	plugins: BTreeMap<String, PluginConfig>,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+	version: u32,
+	stage: Stage,
+	recipe: RecipeInfo<'a>,
+	project_dir: &'a Path,
+	project_name: &'a str,
+	template_sha: Option<&'a str>,
+	variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct RecipeInfo<'a> {
+	name: &'a str,
+	repo: &'a str,
+	branch: Option<&'a str>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Response {
+	#[serde(default)]
+	variables: HashMap<String, String>,
+	#[serde(default)]
+	files: Vec<FileOperation>,
+}
+
+/// A change to the project, relative to its directory.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum FileOperation {
+	Write { path: PathBuf, contents: String },
+	Remove { path: PathBuf },
+}
+
+impl Plugin {
+	/// The `new-hook-*` plugins on `PATH` and the ones configured globally or by `recipe`, which
+	/// wins for plugins both declare.
+	pub fn load(recipe: &recipes::Recipe) -> Result<Vec<Self>> {
+		let discovered = discover(env::var_os("PATH").as_deref());
+		let mut configs = discovered
+			.keys()
+			.map(|name| (name.clone(), PluginConfig::default()))
+			.collect::<BTreeMap<_, _>>();
+		configs.extend(global_config()?.plugins);
+		configs.extend(
+			recipe
+				.config::<BTreeMap<String, PluginConfig>>("plugins")?
+				.unwrap_or_default(),
+		);
+
+		Ok(configs
+			.into_iter()
+			.map(|(name, config)| Self {
+				program: config
+					.path
+					.or_else(|| discovered.get(&name).cloned())
+					.unwrap_or_else(|| PathBuf::from(format!("{PLUGIN_PREFIX}{name}"))),
+				stages: config.stages.unwrap_or_else(|| DEFAULT_STAGES.to_vec()),
+				name,
+			})
+			.filter(|plugin| !plugin.stages.is_empty())
+			.collect())
+	}
+
+	fn request(&self, context: &Context) -> Result<String> {
+		let request = Request {
+			version: PROTOCOL_VERSION,
+			stage: context.stage(),
+			recipe: RecipeInfo {
+				name: &context.recipe.name,
+				repo: &context.recipe.repo,
+				branch: context.recipe.branch.as_deref(),
+			},
+			project_dir: context.project_dir,
+			project_name: context.project_name,
+			template_sha: context.template_sha.as_deref(),
+			variables: context.resolved_variables().into_iter().collect(),
+		};
+
+		serde_json::to_string(&request)
+			.with_context(|| format!("Failed to describe the context to plugin {}", self.name))
+	}
+
+	fn apply(&self, response: Response, context: &Context) -> Result<()> {
+		if context.stage() == Stage::PreClone && !response.files.is_empty() {
+			anyhow::bail!(
+				"Plugin {} can't change files before the template is cloned",
+				self.name
+			);
+		}
+
+		for operation in response.files {
+			let path = match &operation {
+				FileOperation::Write { path, .. } | FileOperation::Remove { path } => path,
+			};
+			if !is_inside(path) {
+				anyhow::bail!(
+					"Plugin {} tried to change {}, outside of the project",
+					self.name,
+					path.display()
+				);
+			}
+
+			let target = context.project_dir.join(path);
+			match &operation {
+				FileOperation::Write { contents, .. } => {
+					if let Some(parent) = target.parent() {
+						fs::create_dir_all(parent).with_context(|| {
+							format!("Failed to create directory {}", parent.display())
+						})?;
+					}
+					fs::write(&target, contents)
+				},
+				FileOperation::Remove { .. } if target.is_dir() => fs::remove_dir_all(&target),
+				FileOperation::Remove { .. } if target.symlink_metadata().is_ok() => {
+					fs::remove_file(&target)
+				},
+				FileOperation::Remove { .. } => Ok(()),
+			}
+			.with_context(|| {
+				format!(
+					"Failed to apply plugin {}'s change to {}",
+					self.name,
+					path.display()
+				)
+			})?;
+
+			context.log(match operation {
+				FileOperation::Write { .. } => format!("Wrote {}", path.display()),
+				FileOperation::Remove { .. } => format!("Removed {}", path.display()),
+			});
+		}
+
+		for (key, value) in response.variables {
+			context.set_variable(key, value);
+		}
+
+		Ok(())
+	}
+}
+
+impl Hook for Plugin {
+	fn id(&self) -> &str {
+		&self.name
+	}
+
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn stage(&self) -> &[Stage] {
+		&self.stages
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		if context.options.dry_run {
+			println!("🧪 Would run plugin {} ({})", self.name, context.stage());
+			return Ok(());
+		}
+
+		let request = self.request(context)?;
+		context.files_changed();
+		// There's no project directory yet before cloning, so those run where `new` was started.
+		let cwd = if context.stage() == Stage::PreClone {
+			env::current_dir().context("Failed to resolve current directory")?
+		} else {
+			context.project_dir.to_path_buf()
+		};
+		let mut child = Command::new(&self.program)
+			.current_dir(&cwd)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::inherit())
+			.spawn()
+			.map_err(|err| match err.kind() {
+				ErrorKind::NotFound => anyhow::anyhow!(
+					"Plugin {} not found. Install {} on your PATH, or set its path under [plugins.{}]",
+					self.name,
+					self.program.display(),
+					self.name
+				),
+				_ => anyhow::Error::new(err).context(format!("Failed to run plugin {}", self.name)),
+			})?;
+
+		if let Some(mut stdin) = child.stdin.take() {
+			// Plugins that don't read their input may have exited already.
+			let _ = stdin.write_all(request.as_bytes());
+		}
+
+		let output = child
+			.wait_with_output()
+			.with_context(|| format!("Failed to run plugin {}", self.name))?;
+		if !output.status.success() {
+			match output.status.code() {
+				Some(code) => anyhow::bail!("Plugin {} failed with exit code {code}", self.name),
+				None => anyhow::bail!("Plugin {} terminated by signal", self.name),
+			}
+		}
+
+		let response = if output.stdout.iter().all(u8::is_ascii_whitespace) {
+			Response::default()
+		} else {
+			serde_json::from_slice(&output.stdout)
+				.with_context(|| format!("Plugin {} answered with invalid JSON", self.name))?
+		};
+
+		self.apply(response, context)
+	}
+}
+
+/// The `new-hook-<name>` executables in `path`, by name. The first one found wins, like a shell
+/// would pick it.
+fn discover(path: Option<&OsStr>) -> BTreeMap<String, PathBuf> {
+	let mut plugins = BTreeMap::new();
+
+	for dir in path.map(env::split_paths).into_iter().flatten() {
+		let Ok(entries) = fs::read_dir(&dir) else {
+			continue;
+		};
+
+		for entry in entries.filter_map(Result::ok) {
+			let path = entry.path();
+			// Windows finds `new-hook-<name>.exe` by its name alone.
+			let file_name = if cfg!(windows) {
+				path.file_stem()
+			} else {
+				path.file_name()
+			};
+			let Some(name) = file_name
+				.and_then(OsStr::to_str)
+				.and_then(|stem| stem.strip_prefix(PLUGIN_PREFIX))
+			else {
+				continue;
+			};
+
+			if !name.is_empty() && is_executable(&path) && !plugins.contains_key(name) {
+				plugins.insert(name.to_string(), path.clone());
+			}
+		}
+	}
+
+	plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+	use std::os::unix::fs::PermissionsExt;
+
+	path.metadata()
+		.is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+	path.is_file()
+		&& path
+			.extension()
+			.is_some_and(|extension| extension.eq_ignore_ascii_case("exe"))
+}
+
+fn global_config() -> Result<GlobalConfig> {
+	let path = recipes::config_root()?.join(GLOBAL_CONFIG);
+	if !path.is_file() {
+		return Ok(GlobalConfig::default());
+	}
+
+	let config =
+		fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+	toml::from_str(&config).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::discover;
+	use std::{env, fs, os::unix::fs::PermissionsExt};
+
+	#[test]
+	fn discovers_plugins_on_path() -> anyhow::Result<()> {
+		let first = tempfile::Builder::new().prefix("new-cli-path").tempdir()?;
+		let second = tempfile::Builder::new().prefix("new-cli-path").tempdir()?;
+		for (dir, name, mode) in [
+			(&first, "new-hook-catalog", 0o755),
+			(&first, "new-hook-notes", 0o644),
+			(&second, "new-hook-catalog", 0o755),
+			(&second, "new-hook-audit", 0o755),
+			(&second, "unrelated", 0o755),
+		] {
+			let path = dir.path().join(name);
+			fs::write(&path, "#!/bin/sh\n")?;
+			fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+		}
+
+		let path = env::join_paths([first.path(), second.path()])?;
+		let plugins = discover(Some(&path));
+
+		assert_eq!(
+			plugins.into_iter().collect::<Vec<_>>(),
+			vec![
+				("audit".to_string(), second.path().join("new-hook-audit")),
+				("catalog".to_string(), first.path().join("new-hook-catalog")),
+			]
+		);
+
+		Ok(())
+	}
+}
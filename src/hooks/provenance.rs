@@ -0,0 +1,78 @@
+use crate::hooks::{Context, Event, Hook, Stage, is_inside};
+use anyhow::{Context as AnyhowContext, Result};
+use serde::Serialize;
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Records where the project came from, the variables it was generated with and what the hooks
+/// did, at the path set in `recipe.provenance`.
+pub struct WriteProvenance;
+
+#[derive(Serialize)]
+struct Provenance<'a> {
+	recipe: &'a str,
+	repo: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	branch: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	template_sha: Option<&'a str>,
+	/// Seconds since the Unix epoch.
+	generated_at: u64,
+	variables: BTreeMap<String, String>,
+	events: Vec<Event>,
+}
+
+impl Hook for WriteProvenance {
+	fn id(&self) -> &'static str {
+		"write_provenance"
+	}
+
+	fn name(&self) -> &'static str {
+		"Write provenance"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::Finalize]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		let Some(path) = context.recipe.config::<PathBuf>("provenance")? else {
+			return Ok(());
+		};
+		if !is_inside(&path) {
+			anyhow::bail!(
+				"Provenance file {} must be inside the project",
+				path.display()
+			);
+		}
+
+		let provenance = Provenance {
+			recipe: &context.recipe.name,
+			repo: &context.recipe.repo,
+			branch: context.recipe.branch.as_deref(),
+			template_sha: context.template_sha.as_deref(),
+			generated_at: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |elapsed| elapsed.as_secs()),
+			variables: context.resolved_variables().into_iter().collect(),
+			events: context.events.borrow().clone(),
+		};
+
+		write(&context.project_dir.join(&path), &provenance)
+			.with_context(|| format!("Failed to write provenance to {}", path.display()))
+	}
+}
+
+fn write(path: &Path, provenance: &Provenance) -> Result<()> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	fs::write(path, toml::to_string(provenance)?)?;
+
+	Ok(())
+}
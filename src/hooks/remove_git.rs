@@ -5,15 +5,23 @@ use std::fs;
 pub struct RemoveGit;
 
 impl Hook for RemoveGit {
+	fn id(&self) -> &'static str {
+		"remove_git"
+	}
+
 	fn name(&self) -> &'static str {
 		"Remove .git directory from template"
 	}
 
 	fn stage(&self) -> &'static [Stage] {
-		&[Stage::PostClone]
+		&[Stage::Render]
 	}
 
 	fn run(&self, context: &Context) -> Result<()> {
+		if context.options.dry_run {
+			return Ok(());
+		}
+
 		let git_dir = context.project_dir.join(".git");
 		if git_dir.is_dir() {
 			fs::remove_dir_all(git_dir)?;
@@ -0,0 +1,351 @@
+use crate::hooks::{Context, Hook, Stage};
+use anyhow::{Context as AnyhowContext, Result};
+use regex::Regex;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::{
+	env, fmt,
+	io::{Read, Seek},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	sync::LazyLock,
+	thread,
+	time::{Duration, Instant},
+};
+
+/// The first version-looking thing in a tool's `--version` output, like `18.17.1` in `v18.17.1`.
+static VERSION: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(r"\d+(\.\d+){1,2}").expect("valid regex"));
+
+/// How long a tool gets to print its version, so one that waits for input can't hang generation.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a tool printing its version is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How to install tools recipes commonly need, for when they're missing.
+const INSTALL_HINTS: &[(&str, &str)] = &[
+	("node", "Install it from https://nodejs.org"),
+	("npm", "It comes with Node.js, from https://nodejs.org"),
+	("pnpm", "Install it with `npm install -g pnpm`"),
+	("yarn", "Install it with `npm install -g yarn`"),
+	("bun", "Install it from https://bun.sh"),
+	("deno", "Install it from https://deno.com"),
+	("cargo", "Install it with rustup, from https://rustup.rs"),
+	("rustc", "Install it with rustup, from https://rustup.rs"),
+	("go", "Install it from https://go.dev/dl"),
+	(
+		"python3",
+		"Install it from https://www.python.org/downloads",
+	),
+	("uv", "Install it from https://docs.astral.sh/uv"),
+	(
+		"docker",
+		"Install it from https://docs.docker.com/get-docker",
+	),
+	("xcodegen", "Install it with `brew install xcodegen`"),
+	("git", "Install it from https://git-scm.com/downloads"),
+];
+
+/// Checks the tools listed under `requires` before anything is cloned, so a missing one doesn't
+/// leave a half-generated project behind. Tools a template manifest adds are checked right after
+/// cloning instead.
+pub struct CheckRequirements;
+
+/// An entry of `requires`, either a plain string like `node >= 18` or a table with a custom hint.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RequirementEntry {
+	Plain(String),
+	Detailed {
+		tool: String,
+		#[serde(default)]
+		version: Option<String>,
+		/// How to install the tool, shown when it's missing or too old.
+		#[serde(default)]
+		hint: Option<String>,
+	},
+}
+
+#[derive(Debug)]
+struct Requirement {
+	tool: String,
+	version: Option<VersionReq>,
+	hint: Option<String>,
+}
+
+impl Hook for CheckRequirements {
+	fn id(&self) -> &'static str {
+		"check_requirements"
+	}
+
+	fn name(&self) -> &'static str {
+		"Check required tools"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::PreClone]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		let Some(entries) = context.recipe.config::<Vec<RequirementEntry>>("requires")? else {
+			return Ok(());
+		};
+
+		let requirements = entries
+			.into_iter()
+			.map(Requirement::try_from)
+			.collect::<Result<Vec<_>>>()?;
+
+		// Checking a version runs the tool, which a dry run doesn't do.
+		if context.options.dry_run {
+			println!(
+				"🧪 Would check these tools are installed: {}",
+				requirements
+					.iter()
+					.map(ToString::to_string)
+					.collect::<Vec<_>>()
+					.join(", ")
+			);
+			return Ok(());
+		}
+
+		let problems = requirements
+			.iter()
+			.filter_map(|requirement| requirement.check().err())
+			.collect::<Vec<_>>();
+
+		match problems.as_slice() {
+			[] => Ok(()),
+			[problem] => anyhow::bail!("{problem}"),
+			problems => anyhow::bail!(
+				"{} required tools are missing or too old:\n{}",
+				problems.len(),
+				problems
+					.iter()
+					.map(|problem| format!("  - {problem}"))
+					.collect::<Vec<_>>()
+					.join("\n")
+			),
+		}
+	}
+}
+
+impl TryFrom<RequirementEntry> for Requirement {
+	type Error = anyhow::Error;
+
+	fn try_from(entry: RequirementEntry) -> Result<Self> {
+		let (tool, version, hint) = match entry {
+			RequirementEntry::Plain(entry) => {
+				let entry = entry.trim();
+				match entry.split_once(char::is_whitespace) {
+					Some((tool, version)) => (tool.to_string(), Some(version.to_string()), None),
+					None => (entry.to_string(), None, None),
+				}
+			},
+			RequirementEntry::Detailed {
+				tool,
+				version,
+				hint,
+			} => (tool, version, hint),
+		};
+
+		if tool.is_empty() {
+			anyhow::bail!("A tool in recipe.requires has no name");
+		}
+		// Checking a tool runs it, so a template can't point at a program of its own.
+		if tool.contains(['/', '\\']) {
+			anyhow::bail!(
+				"`{tool}` in recipe.requires must be the name of a tool on PATH, not a path"
+			);
+		}
+
+		let version = version
+			.map(|version| {
+				VersionReq::parse(version.trim()).with_context(|| {
+					format!("Invalid version requirement `{version}` for {tool} in recipe.requires")
+				})
+			})
+			.transpose()?;
+
+		Ok(Self {
+			tool,
+			version,
+			hint,
+		})
+	}
+}
+
+impl fmt::Display for Requirement {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.version {
+			Some(version) => write!(f, "{} {version}", self.tool),
+			None => write!(f, "{}", self.tool),
+		}
+	}
+}
+
+impl Requirement {
+	/// Describes what's wrong when the tool is missing or doesn't match the version.
+	fn check(&self) -> Result<(), String> {
+		let Some(program) = find_on_path(&self.tool) else {
+			return Err(self.with_hint(format!("{} is not installed", self.tool)));
+		};
+		let Some(requirement) = &self.version else {
+			return Ok(());
+		};
+
+		match installed_version(&program, VERSION_TIMEOUT) {
+			Some(version) if requirement.matches(&version) => Ok(()),
+			Some(version) => Err(self.with_hint(format!(
+				"{} {requirement} is required, but {version} is installed",
+				self.tool
+			))),
+			None => Err(format!(
+				"{} {requirement} is required, but its version couldn't be read from `{} --version`",
+				self.tool, self.tool
+			)),
+		}
+	}
+
+	fn with_hint(&self, problem: String) -> String {
+		let hint = self.hint.as_deref().or_else(|| {
+			INSTALL_HINTS
+				.iter()
+				.find(|(tool, _)| *tool == self.tool)
+				.map(|(_, hint)| *hint)
+		});
+
+		match hint {
+			Some(hint) => format!("{problem}. {hint}"),
+			None => problem,
+		}
+	}
+}
+
+/// Finds `tool` in the directories on `PATH`, trying the executable extensions on Windows.
+fn find_on_path(tool: &str) -> Option<PathBuf> {
+	let extensions = if cfg!(windows) {
+		env::var("PATHEXT")
+			.unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+			.split(';')
+			.map(str::to_string)
+			.collect()
+	} else {
+		vec![String::new()]
+	};
+
+	env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+		extensions
+			.iter()
+			.map(|extension| dir.join(format!("{tool}{extension}")))
+			.find(|path| path.is_file())
+	})
+}
+
+/// Runs `program --version`, giving up after `timeout`. Some tools print their version to stderr,
+/// so both go to a file, which can't fill up and block the tool like a pipe would.
+fn installed_version(program: &Path, timeout: Duration) -> Option<Version> {
+	let mut output = tempfile::tempfile().ok()?;
+	let mut child = Command::new(program)
+		.arg("--version")
+		.stdin(Stdio::null())
+		.stdout(output.try_clone().ok()?)
+		.stderr(output.try_clone().ok()?)
+		.spawn()
+		.ok()?;
+
+	let deadline = Instant::now() + timeout;
+	while child.try_wait().ok()?.is_none() {
+		if Instant::now() >= deadline {
+			let _ = child.kill();
+			let _ = child.wait();
+			return None;
+		}
+		thread::sleep(POLL_INTERVAL);
+	}
+
+	let mut contents = Vec::new();
+	output.rewind().ok()?;
+	output.read_to_end(&mut contents).ok()?;
+	parse_version(&String::from_utf8_lossy(&contents))
+}
+
+/// Reads versions like `v18.17.1`, `cargo 1.80.0 (376290515 2024-07-16)` or `Python 3.12`.
+fn parse_version(output: &str) -> Option<Version> {
+	let version = VERSION.find(output)?.as_str();
+	let mut parts = version
+		.split('.')
+		.map(str::parse::<u64>)
+		.collect::<Result<Vec<_>, _>>()
+		.ok()?;
+	parts.resize(3, 0);
+
+	Some(Version::new(parts[0], parts[1], parts[2]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Requirement, RequirementEntry, installed_version, parse_version};
+	use semver::Version;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn parses_versions_from_version_output() {
+		let cases = [
+			("v18.17.1\n", Version::new(18, 17, 1)),
+			(
+				"cargo 1.80.0 (376290515 2024-07-16)",
+				Version::new(1, 80, 0),
+			),
+			("Python 3.12", Version::new(3, 12, 0)),
+			("Version: 2.38.0", Version::new(2, 38, 0)),
+		];
+
+		for (output, expected) in cases {
+			assert_eq!(parse_version(output), Some(expected), "{output}");
+		}
+		assert_eq!(parse_version("unknown"), None);
+	}
+
+	#[test]
+	fn parses_requirements() -> anyhow::Result<()> {
+		let requirement = Requirement::try_from(RequirementEntry::Plain("node >= 18".to_string()))?;
+		assert_eq!(requirement.tool, "node");
+		let version = requirement.version.expect("a version requirement");
+		assert!(version.matches(&Version::new(20, 1, 0)));
+		assert!(!version.matches(&Version::new(16, 20, 2)));
+
+		let requirement = Requirement::try_from(RequirementEntry::Plain("pnpm".to_string()))?;
+		assert!(requirement.version.is_none());
+
+		assert!(Requirement::try_from(RequirementEntry::Plain("node >= x".to_string())).is_err());
+		assert!(Requirement::try_from(RequirementEntry::Plain("./node".to_string())).is_err());
+
+		Ok(())
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn gives_up_on_tools_that_hang() -> anyhow::Result<()> {
+		use std::{fs, os::unix::fs::PermissionsExt};
+
+		let dir = tempfile::Builder::new().prefix("new-cli-tools").tempdir()?;
+		let (fast, slow) = (dir.path().join("fast"), dir.path().join("slow"));
+		fs::write(&fast, "#!/bin/sh\necho 'fast v1.2.3' >&2\n")?;
+		fs::write(&slow, "#!/bin/sh\nsleep 5\necho 1.0.0\n")?;
+		for tool in [&fast, &slow] {
+			fs::set_permissions(tool, fs::Permissions::from_mode(0o755))?;
+		}
+
+		assert_eq!(
+			installed_version(&fast, Duration::from_secs(5)),
+			Some(Version::new(1, 2, 3))
+		);
+
+		let started = Instant::now();
+		assert_eq!(installed_version(&slow, Duration::from_millis(200)), None);
+		assert!(started.elapsed() < Duration::from_secs(2));
+
+		Ok(())
+	}
+}
@@ -0,0 +1,150 @@
+use crate::hooks::{Context, Hook, Stage};
+use anyhow::{Context as AnyhowContext, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+/// Lists files that only belong to the template, using gitignore syntax.
+const NEWIGNORE: &str = ".newignore";
+/// Stripped from file names after rendering, replacing the file without it.
+const TEMPLATE_SUFFIX: &str = ".new-template";
+
+/// Deletes the files listed in the template's `.newignore`, and the `.newignore` itself.
+pub struct RemoveTemplateFiles;
+
+/// Renames `*.new-template` files over the file they stand in for, like `README.md`.
+pub struct ApplyTemplateFiles;
+
+impl Hook for RemoveTemplateFiles {
+	fn id(&self) -> &'static str {
+		"remove_template_files"
+	}
+
+	fn name(&self) -> &'static str {
+		"Remove template-only files"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::PostClone]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		let newignore = context.project_dir.join(NEWIGNORE);
+		if !newignore.is_file() {
+			return Ok(());
+		}
+
+		let mut builder = GitignoreBuilder::new(context.project_dir);
+		if let Some(err) = builder.add(&newignore) {
+			return Err(err).context("Failed to read .newignore");
+		}
+		let gitignore = builder.build().context("Failed to parse .newignore")?;
+
+		remove_ignored(context.project_dir, context.project_dir, &gitignore)?;
+		fs::remove_file(&newignore).context("Failed to remove .newignore")?;
+
+		Ok(())
+	}
+}
+
+impl Hook for ApplyTemplateFiles {
+	fn id(&self) -> &'static str {
+		"apply_template_files"
+	}
+
+	fn name(&self) -> &'static str {
+		"Apply .new-template files"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::Render]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		for path in find_template_files(context.project_dir)? {
+			let name = path
+				.file_name()
+				.and_then(|name| name.to_str())
+				.and_then(|name| name.strip_suffix(TEMPLATE_SUFFIX))
+				.unwrap_or_default();
+			let target = path.with_file_name(name);
+
+			if target.is_dir() {
+				fs::remove_dir_all(&target)
+			} else if target.symlink_metadata().is_ok() {
+				fs::remove_file(&target)
+			} else {
+				Ok(())
+			}
+			.with_context(|| format!("Failed to replace {}", target.display()))?;
+
+			fs::rename(&path, &target)
+				.with_context(|| format!("Failed to rename {}", path.display()))?;
+			context.files_changed();
+		}
+
+		Ok(())
+	}
+}
+
+pub(super) fn remove_ignored(root: &Path, dir: &Path, gitignore: &Gitignore) -> Result<()> {
+	for entry in read_dir(dir)? {
+		let path = entry.path();
+		let is_dir = entry.file_type().is_ok_and(|ft| ft.is_dir());
+		let relative = path.strip_prefix(root).unwrap_or(&path);
+
+		if gitignore.matched(relative, is_dir).is_ignore() {
+			if is_dir {
+				fs::remove_dir_all(&path)
+			} else {
+				fs::remove_file(&path)
+			}
+			.with_context(|| format!("Failed to remove {}", relative.display()))?;
+		} else if is_dir {
+			remove_ignored(root, &path, gitignore)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Finds `*.new-template` entries, deepest first so renaming one never moves another.
+fn find_template_files(dir: &Path) -> Result<Vec<PathBuf>> {
+	let mut found = Vec::new();
+
+	for entry in read_dir(dir)? {
+		let path = entry.path();
+
+		if entry.file_type().is_ok_and(|ft| ft.is_dir()) {
+			found.extend(find_template_files(&path)?);
+		}
+
+		if path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.is_some_and(|name| {
+				name.len() > TEMPLATE_SUFFIX.len() && name.ends_with(TEMPLATE_SUFFIX)
+			}) {
+			found.push(path);
+		}
+	}
+
+	Ok(found)
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<fs::DirEntry>> {
+	let mut entries = Vec::new();
+
+	for entry in
+		fs::read_dir(dir).with_context(|| format!("Failed to read directory {}", dir.display()))?
+	{
+		let entry = entry.with_context(|| format!("Failed to read directory {}", dir.display()))?;
+		if entry.file_name() != ".git" {
+			entries.push(entry);
+		}
+	}
+
+	Ok(entries)
+}
@@ -0,0 +1,189 @@
+use crate::{
+	hooks::{Context, Hook, Stage, commands},
+	recipes,
+};
+use anyhow::{Context as AnyhowContext, Result};
+use inquire::Confirm;
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::Write,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+/// Where templates keep their scripts, named after the stage they run in, like `pre_render.py`.
+pub(super) const SCRIPTS_DIR: &str = ".new/hooks";
+/// Remembers which template revisions may run their scripts.
+const TRUST_STORE: &str = "trusted-templates.toml";
+
+/// Runs the template's own `pre_render` and `post_render` scripts, with the answers as JSON on
+/// stdin, and removes them from the project afterwards.
+pub struct TemplateScripts;
+
+impl Hook for TemplateScripts {
+	fn id(&self) -> &'static str {
+		"template_scripts"
+	}
+
+	fn name(&self) -> &'static str {
+		"Run Template Scripts"
+	}
+
+	fn stage(&self) -> &'static [Stage] {
+		&[Stage::PreRender, Stage::PostRender]
+	}
+
+	fn run(&self, context: &Context) -> Result<()> {
+		let scripts_dir = context.project_dir.join(SCRIPTS_DIR);
+
+		if let Some(script) = find_script(&scripts_dir, context.stage())? {
+			context.files_changed();
+			run_script(&script, context)?;
+		}
+
+		if context.stage() == Stage::PostRender && scripts_dir.is_dir() {
+			fs::remove_dir_all(&scripts_dir).context("Failed to remove the template's scripts")?;
+			if let Some(parent) = scripts_dir.parent() {
+				// Only removes `.new` when nothing else is left in it.
+				let _ = fs::remove_dir(parent);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Finds the script for `stage`, named after it with or without an extension.
+fn find_script(scripts_dir: &Path, stage: Stage) -> Result<Option<PathBuf>> {
+	if !scripts_dir.is_dir() {
+		return Ok(None);
+	}
+
+	let stage = stage.to_string();
+	let scripts = fs::read_dir(scripts_dir)
+		.and_then(Iterator::collect::<Result<Vec<_>, _>>)
+		.context("Failed to read the template's scripts")?
+		.into_iter()
+		.map(|entry| entry.path())
+		.filter(|path| {
+			path.is_file() && path.file_stem().and_then(|stem| stem.to_str()) == Some(&stage)
+		})
+		.collect::<Vec<_>>();
+
+	match scripts.as_slice() {
+		[] => Ok(None),
+		[script] => Ok(Some(script.clone())),
+		_ => anyhow::bail!("The template has more than one {stage} script"),
+	}
+}
+
+fn run_script(script: &Path, context: &Context) -> Result<()> {
+	let stage = context.stage();
+
+	if context.options.dry_run {
+		println!("🧪 Would run the template's {stage} script");
+		return Ok(());
+	}
+
+	if !is_trusted(context)? {
+		println!("⏭️  Skipping the template's {stage} script, since the template isn't trusted");
+		return Ok(());
+	}
+
+	let interpreters = context
+		.recipe
+		.config::<BTreeMap<String, String>>("scripts")?
+		.unwrap_or_default();
+	let mut command = match interpreters.get(&stage.to_string()) {
+		Some(interpreter) => {
+			let mut parts = interpreter.split_whitespace();
+			let program = parts.next().with_context(|| {
+				format!("The interpreter for the {stage} script in recipe.scripts is empty")
+			})?;
+
+			let mut command = Command::new(program);
+			command.args(parts).arg(script);
+			command
+		},
+		None => Command::new(script),
+	};
+
+	let mut child = command
+		.current_dir(context.project_dir)
+		.envs(commands::environment(context))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::inherit())
+		.stderr(Stdio::inherit())
+		.spawn()
+		.with_context(|| format!("Failed to run the template's {stage} script"))?;
+
+	if let Some(mut stdin) = child.stdin.take() {
+		let answers = context
+			.resolved_variables()
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+		// Scripts that don't read their input may have exited already.
+		let _ = stdin.write_all(serde_json::to_string(&answers)?.as_bytes());
+	}
+
+	let status = child
+		.wait()
+		.with_context(|| format!("Failed to run the template's {stage} script"))?;
+
+	match status.code() {
+		Some(0) => {
+			context.log(format!("Ran the template's {stage} script"));
+			Ok(())
+		},
+		Some(code) => anyhow::bail!("The template's {stage} script failed with exit code {code}"),
+		None => anyhow::bail!("The template's {stage} script was terminated by signal"),
+	}
+}
+
+/// Whether the template's revision may run its own code, asking the first time and remembering
+/// it. A dry run runs nothing, so it never asks, and previews everything the template would run.
+pub fn is_trusted(context: &Context) -> Result<bool> {
+	if let Some(trusted) = context.options.trust_template {
+		return Ok(trusted);
+	}
+
+	let revision = format!(
+		"{}@{}",
+		context.recipe.repo,
+		context.template_sha.as_deref().unwrap_or("unknown")
+	);
+	let path = recipes::config_root()?.join(TRUST_STORE);
+	let mut store = if path.is_file() {
+		fs::read_to_string(&path)
+			.with_context(|| format!("Failed to read trust store {}", path.display()))?
+			.parse::<toml::Table>()
+			.with_context(|| format!("Failed to parse trust store {}", path.display()))?
+	} else {
+		toml::Table::new()
+	};
+
+	if let Some(trusted) = store.get(&revision).and_then(toml::Value::as_bool) {
+		return Ok(trusted);
+	}
+	if context.options.dry_run {
+		return Ok(true);
+	}
+
+	let trusted = Confirm::new(&format!(
+		"The template {revision} wants to run scripts or commands on your machine. Do you trust it?"
+	))
+	.with_default(false)
+	.prompt()
+	.context("Failed to prompt for trusting the template")?;
+
+	store.insert(revision, toml::Value::Boolean(trusted));
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)
+			.with_context(|| format!("Failed to create directory {}", parent.display()))?;
+	}
+	fs::write(&path, toml::to_string(&store)?)
+		.with_context(|| format!("Failed to write trust store {}", path.display()))?;
+
+	Ok(trusted)
+}
@@ -0,0 +1,36 @@
+use anyhow::{Context, Result};
+use signal_hook::{consts::SIGINT, flag};
+use std::sync::{
+	Arc, OnceLock,
+	atomic::{AtomicBool, Ordering},
+};
+
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Catches Ctrl-C, so generation can stop between steps and clean up after itself.
+/// Pressing Ctrl-C a second time exits right away.
+pub fn install() -> Result<()> {
+	if INTERRUPTED.get().is_some() {
+		return Ok(());
+	}
+
+	let interrupted = Arc::new(AtomicBool::new(false));
+	flag::register_conditional_shutdown(SIGINT, 130, Arc::clone(&interrupted))
+		.context("Failed to install Ctrl-C handler")?;
+	flag::register(SIGINT, Arc::clone(&interrupted)).context("Failed to install Ctrl-C handler")?;
+
+	let _ = INTERRUPTED.set(interrupted);
+	Ok(())
+}
+
+/// Fails if Ctrl-C was pressed since [`install`] was called.
+pub fn check() -> Result<()> {
+	if INTERRUPTED
+		.get()
+		.is_some_and(|interrupted| interrupted.load(Ordering::Relaxed))
+	{
+		anyhow::bail!("Interrupted");
+	}
+
+	Ok(())
+}
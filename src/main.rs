@@ -3,6 +3,7 @@
 mod commands;
 mod git;
 mod hooks;
+mod interrupt;
 mod recipes;
 
 use anyhow::Result;
@@ -29,6 +30,7 @@ fn main() -> Result<()> {
 		None => commands::init::run(&cli.init),
 		Some(Commands::List) => commands::list::run(),
 		Some(Commands::Init(args)) => commands::init::run(&args),
+		Some(Commands::Add(args)) => commands::add::run(&args),
 		Some(Commands::Edit { editor }) => commands::edit::run(editor),
 	}
 }
@@ -8,19 +8,40 @@ use std::{
 	path::{Path, PathBuf},
 };
 
-use crate::{git, hooks};
+use crate::{
+	git,
+	hooks::{self, Hook},
+	interrupt,
+};
+
+/// Where a template can declare its own recipe configuration, in order of precedence.
+const MANIFEST_PATHS: &[&str] = &["new.toml", ".new/recipe.toml"];
+/// Manifest keys that run code on the user's machine, only kept when the template is trusted.
+const TRUSTED_KEYS: &[&str] = &["commands", "plugins", "scripts"];
 
 #[derive(Debug, Deserialize)]
 struct RecipeDeclaration {
 	recipe: Recipe,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+	/// Creates a new project.
+	#[default]
+	Project,
+	/// Adds files to an existing project, like CI or a Dockerfile.
+	Addon,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Recipe {
 	pub name: String,
 	pub repo: String,
 	#[serde(default)]
 	pub branch: Option<String>,
+	#[serde(default)]
+	pub kind: Kind,
 	#[eserde(compat)]
 	#[serde(default, flatten)]
 	pub extra: toml::value::Table,
@@ -40,13 +61,147 @@ impl Recipe {
 			})
 	}
 
-	pub fn run(&self, directory: &Path, name: &str) -> Result<()> {
-		let registry = hooks::Registry::with_defaults();
-		let context = hooks::Context::new(self, directory, name);
+	/// Runs every stage in `directory`, for projects that are generated where they'll stay.
+	pub fn run(&self, directory: &Path, name: &str, options: &hooks::Options) -> Result<()> {
+		let (recipe, sha) = self.clone_template(directory, name, options)?;
+		let registry = hooks::Registry::for_recipe(&recipe)?;
+		let context =
+			hooks::Context::new(&recipe, options, directory, name)?.with_template_sha(sha);
+
+		registry.run_stages(
+			&[hooks::Stage::RENDER, hooks::Stage::FINISH].concat(),
+			&context,
+		)
+	}
+
+	/// Renders the recipe in `staging_dir`, lets `merge` move it to `project_dir`, and then runs the
+	/// remaining stages there, so commands see the project where it'll stay.
+	pub fn apply(
+		&self,
+		staging_dir: &Path,
+		project_dir: &Path,
+		name: &str,
+		options: &hooks::Options,
+		merge: impl FnOnce() -> Result<()>,
+	) -> Result<()> {
+		let (recipe, sha) = self.clone_template(staging_dir, name, options)?;
+		let registry = hooks::Registry::for_recipe(&recipe)?;
+		let context =
+			hooks::Context::new(&recipe, options, staging_dir, name)?.with_template_sha(sha);
+
+		registry.run_stages(hooks::Stage::RENDER, &context)?;
+		if let Err(err) = merge() {
+			registry.run_on_error(&context);
+			return Err(err);
+		}
+
+		let context = context.moved_to(project_dir);
+		registry.run_stages(hooks::Stage::FINISH, &context)
+	}
+
+	/// Clones the template into `directory`, returning this recipe merged with its manifest and
+	/// the SHA of the cloned commit.
+	fn clone_template(
+		&self,
+		directory: &Path,
+		name: &str,
+		options: &hooks::Options,
+	) -> Result<(Self, String)> {
+		let registry = hooks::Registry::for_recipe(self)?;
+		let context = hooks::Context::new(self, options, directory, name)?;
 
 		registry.run(hooks::Stage::PreClone, &context)?;
-		git::clone_repo(&self.repo, self.branch.as_deref(), directory)?;
-		registry.run(hooks::Stage::PostClone, &context)
+		let sha = git::clone_repo(&self.repo, self.branch.as_deref(), directory)?;
+		interrupt::check()?;
+
+		let context = context.with_template_sha(sha.clone());
+		let recipe = self.with_manifest(directory, || hooks::is_trusted(&context))?;
+
+		// Tools the manifest requires can only be checked now, but still before anything renders.
+		if recipe.extra.get("requires") != self.extra.get("requires") {
+			let context = hooks::Context::new(&recipe, options, directory, name)?;
+			hooks::Registry::for_recipe(&recipe)?.run_one(
+				hooks::CheckRequirements.id(),
+				hooks::Stage::PostClone,
+				&context,
+			)?;
+		}
+
+		Ok((recipe, sha))
+	}
+
+	/// Merges the template's manifest under this recipe, which wins on conflicts, and removes it
+	/// from the project. Commands, plugins and script interpreters from the manifest are dropped
+	/// unless `is_trusted` says the template may run code.
+	fn with_manifest(
+		&self,
+		directory: &Path,
+		is_trusted: impl FnOnce() -> Result<bool>,
+	) -> Result<Self> {
+		let mut recipe = self.clone();
+
+		let Some(path) = MANIFEST_PATHS
+			.iter()
+			.map(|path| directory.join(path))
+			.find(|path| path.is_file())
+		else {
+			return Ok(recipe);
+		};
+
+		let manifest = fs::read_to_string(&path)
+			.with_context(|| format!("Failed to read template manifest {}", path.display()))?
+			.parse::<toml::Table>()
+			.with_context(|| format!("Failed to parse template manifest {}", path.display()))?;
+
+		let mut extra = match manifest.get("recipe") {
+			Some(toml::Value::Table(table)) => table.clone(),
+			Some(_) => anyhow::bail!("Template manifest must declare a [recipe] table"),
+			None => toml::Table::new(),
+		};
+		for key in ["name", "repo", "branch", "kind"] {
+			extra.remove(key);
+		}
+
+		let untrusted = TRUSTED_KEYS
+			.iter()
+			.copied()
+			.filter(|key| extra.contains_key(*key))
+			.collect::<Vec<_>>();
+		if !untrusted.is_empty() && !is_trusted()? {
+			println!(
+				"⏭️  Ignoring the template's {}, since the template isn't trusted",
+				untrusted.join(", ")
+			);
+			for key in untrusted {
+				extra.remove(key);
+			}
+		}
+
+		merge(&mut extra, recipe.extra);
+		recipe.extra = extra;
+
+		fs::remove_file(&path)
+			.with_context(|| format!("Failed to remove template manifest {}", path.display()))?;
+		if let Some(parent) = path.parent().filter(|parent| *parent != directory) {
+			// Only removes `.new` when nothing else is left in it.
+			let _ = fs::remove_dir(parent);
+		}
+
+		Ok(recipe)
+	}
+}
+
+/// Deep-merges `overrides` into `base`, replacing anything that isn't a table on both sides.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+	for (key, value) in overrides {
+		match (base.get_mut(&key), value) {
+			(Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+				merge(base, overrides);
+			},
+			(_, value) => {
+				base.insert(key, value);
+			},
+		}
 	}
 }
 
@@ -85,12 +240,15 @@ pub fn list() -> Result<HashMap<PathBuf, Result<Recipe, ReadError>>> {
 	Ok(recipes)
 }
 
-pub fn recipes_dir() -> Result<PathBuf> {
+/// Where recipes and everything else the CLI remembers are kept.
+pub fn config_root() -> Result<PathBuf> {
 	let config_root = config_dir().context("Unable to resolve configuration directory")?;
 
-	Ok(Path::new(&config_root)
-		.join("build.m1guelpf.new")
-		.join("recipes"))
+	Ok(Path::new(&config_root).join("build.m1guelpf.new"))
+}
+
+pub fn recipes_dir() -> Result<PathBuf> {
+	Ok(config_root()?.join("recipes"))
 }
 
 fn recipe_files() -> Result<Vec<PathBuf>> {
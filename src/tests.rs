@@ -1,23 +1,84 @@
-use crate::recipes::Recipe;
-use anyhow::Result;
+use crate::{
+	commands::{
+		add,
+		init::{self, Conflict},
+	},
+	git::{self, Change},
+	hooks::Options,
+	recipes::{Kind, Recipe},
+};
+use anyhow::{Context, Result};
 use git2::{Repository, Signature};
-use std::{fs, path::Path};
-use tempdir::TempDir;
+use std::{
+	collections::BTreeMap,
+	env, fs,
+	path::{Path, PathBuf},
+	process::Command,
+	thread,
+	time::Duration,
+};
+use tempfile::TempDir;
 
-fn init_template_repo() -> Result<TempDir> {
-	let template_dir = TempDir::new("new-cli-template")?;
-	let root = template_dir.path();
+/// Set in the processes that [`in_isolated_home`] starts.
+const ISOLATED_HOME: &str = "NEW_CLI_TEST_ISOLATED_HOME";
+
+fn temp_dir(prefix: &str) -> Result<TempDir> {
+	Ok(tempfile::Builder::new().prefix(prefix).tempdir()?)
+}
+
+/// Whether this is the copy of `test` that runs with a `HOME` holding only `gitconfig`. Otherwise,
+/// runs that copy in a new process, so the user's own configuration can't leak into it.
+fn in_isolated_home(test: &str, gitconfig: &str) -> Result<bool> {
+	if env::var_os(ISOLATED_HOME).is_some() {
+		return Ok(true);
+	}
+
+	let home = temp_dir("new-cli-home")?;
+	fs::write(home.path().join(".gitconfig"), gitconfig)?;
+
+	let output = Command::new(env::current_exe()?)
+		.args([test, "--exact", "--nocapture"])
+		.env(ISOLATED_HOME, "1")
+		.env("HOME", home.path())
+		.env("USERPROFILE", home.path())
+		.env("XDG_CONFIG_HOME", home.path().join(".config"))
+		.env("XDG_STATE_HOME", home.path().join(".local").join("state"))
+		.env("GIT_CONFIG_GLOBAL", home.path().join(".gitconfig"))
+		.env("GIT_CONFIG_NOSYSTEM", "1")
+		.output()?;
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(
+		output.status.success() && stdout.contains("1 passed"),
+		"{stdout}{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
 
-	fs::write(root.join("README.md"), "Hello {{NAME}} ({{APP_ID}})")?;
+	Ok(false)
+}
 
-	let nested_dir = root.join("{{NAME}}");
-	fs::create_dir_all(&nested_dir)?;
-	fs::write(nested_dir.join("config-{{APP_ID}}.txt"), "id={{APP_ID}}")?;
+fn init_template_repo() -> Result<TempDir> {
+	commit_template(&[
+		("README.md", "Hello {{NAME}} ({{APP_ID}})"),
+		("{{NAME}}/config-{{APP_ID}}.txt", "id={{APP_ID}}"),
+	])
+}
 
+fn commit_template<C: AsRef<[u8]>>(files: &[(&str, C)]) -> Result<TempDir> {
+	let template_dir = temp_dir("new-cli-template")?;
+	let root = template_dir.path();
 	let repo = Repository::init(root)?;
 	let mut index = repo.index()?;
-	index.add_path(Path::new("README.md"))?;
-	index.add_path(Path::new("{{NAME}}/config-{{APP_ID}}.txt"))?;
+
+	for (path, contents) in files {
+		let file = root.join(path);
+		if let Some(parent) = file.parent() {
+			fs::create_dir_all(parent)?;
+		}
+
+		fs::write(&file, contents)?;
+		index.add_path(Path::new(path))?;
+	}
+
 	index.write()?;
 
 	let tree_id = index.write_tree()?;
@@ -48,6 +109,7 @@ fn build_recipe(repo_path: &Path) -> Recipe {
 		name: "local".to_string(),
 		repo: repo_path.to_string_lossy().to_string(),
 		branch: None,
+		kind: Kind::Project,
 		extra,
 	}
 }
@@ -55,11 +117,12 @@ fn build_recipe(repo_path: &Path) -> Recipe {
 #[test]
 fn recipe_run_applies_hooks_end_to_end() -> Result<()> {
 	let template_dir = init_template_repo()?;
-	let project_root = TempDir::new("new-cli-project")?;
+	let project_root = temp_dir("new-cli-project")?;
 	let project_dir = project_root.path().join("MyProject");
+	let options = Options::default();
 
 	let recipe = build_recipe(template_dir.path());
-	recipe.run(&project_dir, "MyProject")?;
+	recipe.run(&project_dir, "MyProject", &options)?;
 
 	assert!(!project_dir.join(".git").exists());
 	assert!(project_dir.join("MyProject").is_dir());
@@ -77,3 +140,998 @@ fn recipe_run_applies_hooks_end_to_end() -> Result<()> {
 
 	Ok(())
 }
+
+/// A template committed from `files`, and a recipe for it made of the `[recipe]` keys in `config`.
+fn template_recipe<C: AsRef<[u8]>>(files: &[(&str, C)], config: &str) -> Result<(TempDir, Recipe)> {
+	let template_dir = commit_template(files)?;
+	let recipe = Recipe {
+		name: "local".to_string(),
+		repo: template_dir.path().to_string_lossy().to_string(),
+		branch: None,
+		kind: Kind::Project,
+		extra: toml::from_str(config)?,
+	};
+
+	Ok((template_dir, recipe))
+}
+
+/// Where a test generates `MyProject`, inside a temporary directory that lives as long as it.
+struct Project {
+	root: TempDir,
+	dir: PathBuf,
+}
+
+impl Project {
+	fn new() -> Result<Self> {
+		let root = temp_dir("new-cli-project")?;
+		let dir = root.path().join("MyProject");
+
+		Ok(Self { root, dir })
+	}
+
+	/// Generates the project from a template made of `files`, with the `[recipe]` keys in `config`.
+	fn generate<C: AsRef<[u8]>>(
+		&self,
+		files: &[(&str, C)],
+		config: &str,
+		options: &Options,
+	) -> Result<()> {
+		let (_template_dir, recipe) = template_recipe(files, config)?;
+		recipe.run(&self.dir, "MyProject", options)
+	}
+
+	fn read(&self, path: &str) -> Result<String> {
+		fs::read_to_string(self.dir.join(path)).with_context(|| format!("Failed to read {path}"))
+	}
+
+	fn has(&self, path: &str) -> bool {
+		self.dir.join(path).exists()
+	}
+}
+
+/// Generates `MyProject` from a template made of `files`, with the `[recipe]` keys in `config`.
+fn generate<C: AsRef<[u8]>>(files: &[(&str, C)], config: &str) -> Result<Project> {
+	let project = Project::new()?;
+	project.generate(files, config, &Options::default())?;
+
+	Ok(project)
+}
+
+#[test]
+fn recipe_run_renders_placeholders() -> Result<()> {
+	let files = [
+		(
+			"README.md",
+			"# {{NAME}}\n\nRender with `\\{{NAME}}`, match with `/\\{{2}/`\n{{ APP_ID }} and {{TEAM_ID}}",
+		),
+		("{{ NAME }}.txt", "{{NAME}}"),
+		(
+			".github/workflows/ci.yml",
+			"env:\n  TOKEN: ${{ secrets.NPM_TOKEN }}\n  APP: {{NAME}}",
+		),
+		("package.json", "{ \"version\": \"0.0.0-template\" }"),
+		("CHANGELOG.md", "## 0.0.0-template"),
+	];
+	// Without prompts, placeholders without a value are left as they are.
+	let config = r#"
+		provenance = "provenance.toml"
+		hooks = { prompt_placeholders = false }
+		replacements = { APP_ID = "com.example.app" }
+		placeholders = { exclude = [".github/**"] }
+
+		[[regex_replacements]]
+		pattern = '(version"?\s*[:=]\s*)"0\.0\.0-template"'
+		replacement = '${1}"0.1.0-{{APP_ID}}"'
+		files = "*.{json,toml}"
+	"#;
+	let project = generate(&files, config)?;
+
+	assert_eq!(
+		project.read("README.md")?,
+		"# MyProject\n\nRender with `{{NAME}}`, match with `/\\{{2}/`\ncom.example.app and {{TEAM_ID}}"
+	);
+	assert_eq!(project.read("MyProject.txt")?, "MyProject");
+	assert_eq!(
+		project.read(".github/workflows/ci.yml")?,
+		"env:\n  TOKEN: ${{ secrets.NPM_TOKEN }}\n  APP: {{NAME}}"
+	);
+	assert_eq!(
+		project.read("package.json")?,
+		"{ \"version\": \"0.1.0-com.example.app\" }"
+	);
+	assert_eq!(project.read("CHANGELOG.md")?, "## 0.0.0-template");
+
+	let provenance = project.read("provenance.toml")?;
+	assert!(provenance.contains("Left TEAM_ID without a value"));
+	assert!(!provenance.contains("Left APP_ID"));
+
+	let strict = Options {
+		strict: true,
+		..Options::default()
+	};
+	let err = Project::new()?
+		.generate(&files, config, &strict)
+		.expect_err("strict mode should fail on leftovers");
+	assert!(
+		format!("{err:#}").contains("Placeholders left without a value: TEAM_ID"),
+		"{err:#}"
+	);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_renames_literals_in_every_case() -> Result<()> {
+	let project = generate(
+		&[
+			(
+				"TemplateApp/TemplateApp.swift",
+				"struct TemplateApp {}\nlet bundle = \"com.example.template\"\nlet slug = \"template-app\"",
+			),
+			(
+				"template_app.py",
+				"TEMPLATE_APP = True\nWIDGET_DIR = widgets.Widget",
+			),
+		],
+		r#"
+		replacements = { APP_ID = "com.example.app" }
+
+		[rename]
+		TemplateApp = "{{NAME | pascal_case}}"
+		"com.example.template" = "{{APP_ID}}"
+		Widget = "Gadget"
+		"#,
+	)?;
+
+	assert_eq!(
+		project.read("MyProject/MyProject.swift")?,
+		"struct MyProject {}\nlet bundle = \"com.example.app\"\nlet slug = \"my-project\""
+	);
+	// A single word is only renamed as written, leaving unrelated identifiers alone.
+	assert_eq!(
+		project.read("my_project.py")?,
+		"MY_PROJECT = True\nWIDGET_DIR = widgets.Gadget"
+	);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_keeps_renamed_paths_inside_the_project() -> Result<()> {
+	let options = Options::default();
+	let escaping = [("{{APP_ID}}.txt", "contents")];
+
+	let project = Project::new()?;
+	let err = project
+		.generate(
+			&escaping,
+			r#"replacements = { APP_ID = "../escaped" }"#,
+			&options,
+		)
+		.expect_err("the rename should be refused");
+	assert!(format!("{err:#}").contains("nested directories"), "{err:#}");
+	assert!(!project.root.path().join("escaped.txt").exists());
+
+	let project = Project::new()?;
+	let err = project
+		.generate(
+			&escaping,
+			r#"
+			replacements = { APP_ID = "../escaped" }
+			placeholders = { nested_paths = true }
+			"#,
+			&options,
+		)
+		.expect_err("the rename should be refused");
+	assert!(
+		format!("{err:#}").contains("not a valid file name"),
+		"{err:#}"
+	);
+	assert!(!project.root.path().join("escaped.txt").exists());
+
+	let err = Project::new()?
+		.generate(
+			&[("{{APP_ID}}.txt", "first"), ("{{NAME}}.txt", "second")],
+			r#"replacements = { APP_ID = "MyProject" }"#,
+			&options,
+		)
+		.expect_err("colliding renames should fail");
+	assert!(format!("{err:#}").contains("already exists"), "{err:#}");
+
+	let project = generate(
+		&[
+			("src/{{APP_ID}}/Main.java", "package {{APP_ID}};"),
+			("src/com/Shared.java", "shared"),
+		],
+		r#"
+		replacements = { APP_ID = "com/example" }
+		placeholders = { nested_paths = true }
+		"#,
+	)?;
+	assert!(project.has("src/com/example/Main.java"));
+	assert!(project.has("src/com/Shared.java"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_preserves_large_and_encoded_files() -> Result<()> {
+	let filler = "x".repeat(1023) + "\n";
+	let large = format!("{{{{NAME}}}}\n{}{{{{NAME}}}}\n", filler.repeat(9 * 1024));
+	let mut utf16 = vec![0xFF, 0xFE];
+	utf16.extend(
+		"VALUE \"ProductName\", \"{{NAME}}\""
+			.encode_utf16()
+			.flat_map(u16::to_le_bytes),
+	);
+
+	let project = generate(
+		&[
+			("assets/large.txt", large.into_bytes()),
+			("app.rc", utf16),
+			("strings.txt", b"caf\xe9 {{NAME}}".to_vec()),
+		],
+		r#"placeholders = { encodings = { "*.txt" = "latin1" } }"#,
+	)?;
+
+	let large = project.read("assets/large.txt")?;
+	assert!(large.starts_with("MyProject\nxxx"));
+	assert!(large.ends_with("xxx\nMyProject\n"));
+	assert!(!project.has("assets/.large.txt.new-tmp"));
+
+	let mut expected = vec![0xFF, 0xFE];
+	expected.extend(
+		"VALUE \"ProductName\", \"MyProject\""
+			.encode_utf16()
+			.flat_map(u16::to_le_bytes),
+	);
+	assert_eq!(fs::read(project.dir.join("app.rc"))?, expected);
+	assert_eq!(
+		fs::read(project.dir.join("strings.txt"))?,
+		b"caf\xe9 MyProject"
+	);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_removes_template_only_files() -> Result<()> {
+	let project = generate(
+		&[
+			(
+				".gitattributes",
+				"tests/fixtures export-ignore\n.github/ export-ignore\n*.snap -text\n",
+			),
+			(".github/workflows/template.yml", "name: {{NAME}}"),
+			("tests/fixtures/{{NAME}}.txt", "{{NAME}}"),
+			("tests/main.rs", "// {{NAME}}"),
+			("snapshots/main.snap", "{{NAME}}"),
+			(
+				".newignore",
+				"/README.md\nscreenshots/\n*.template-ci.yml\n!keep.template-ci.yml\n",
+			),
+			("README.md", "# Template"),
+			("README.md.new-template", "# {{NAME}}"),
+			("screenshots/home.png", "not really a png"),
+			("ci/test.template-ci.yml", "on: push"),
+			("ci/keep.template-ci.yml", "on: push"),
+			("docs/README.md", "# Docs"),
+		],
+		"",
+	)?;
+
+	assert!(!project.has(".github"));
+	assert!(!project.has("tests/fixtures"));
+	assert_eq!(project.read("tests/main.rs")?, "// MyProject");
+	assert_eq!(project.read("snapshots/main.snap")?, "{{NAME}}");
+
+	assert_eq!(project.read("README.md")?, "# MyProject");
+	assert!(!project.has("README.md.new-template"));
+	assert!(!project.has(".newignore"));
+	assert!(!project.has("screenshots"));
+	assert!(!project.has("ci/test.template-ci.yml"));
+	assert!(project.has("ci/keep.template-ci.yml"));
+	assert!(project.has("docs/README.md"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_merges_template_manifest() -> Result<()> {
+	let files = [
+		(
+			"new.toml",
+			r#"[recipe]
+commands = ["echo manifest > manifest.txt"]
+
+[recipe.replacements]
+APP_ID = "com.template.app"
+TEAM_ID = "TEAM123"
+DOCKER = "no"
+
+[[recipe.conditional_files]]
+when = "DOCKER"
+paths = ["/Dockerfile", "docker/"]
+"#,
+		),
+		("config.txt", "{{APP_ID}} {{TEAM_ID}}"),
+		("Dockerfile", "FROM {{NAME}}"),
+		("docker/compose.yml", "services: {}"),
+	];
+
+	let project = Project::new()?;
+	let trusted = Options {
+		trust_template: Some(true),
+		..Options::default()
+	};
+	project.generate(
+		&files,
+		r#"
+		commands = ["echo done > done.txt"]
+		replacements = { APP_ID = "com.example.app" }
+		"#,
+		&trusted,
+	)?;
+
+	assert_eq!(project.read("config.txt")?, "com.example.app TEAM123");
+	assert!(project.has("done.txt"));
+	assert!(!project.has("manifest.txt"));
+	assert!(!project.has("new.toml"));
+	assert!(!project.has("Dockerfile"));
+	assert!(!project.has("docker"));
+
+	// Without trust, the manifest's values are still used, but not its commands.
+	let project = Project::new()?;
+	let untrusted = Options {
+		trust_template: Some(false),
+		..Options::default()
+	};
+	project.generate(&files, "", &untrusted)?;
+
+	assert_eq!(project.read("config.txt")?, "com.template.app TEAM123");
+	assert!(!project.has("manifest.txt"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_dry_run_keeps_repository_and_skips_commands() -> Result<()> {
+	let project = Project::new()?;
+	let options = Options {
+		dry_run: true,
+		..Options::default()
+	};
+	project.generate(
+		&[
+			("{{NAME}}.txt", "Hello"),
+			("README.md", "# {{NAME}}"),
+			("LICENSE", "MIT"),
+		],
+		r#"
+		commands = ["echo done > done.txt"]
+		requires = ["new-cli-missing-tool"]
+		"#,
+		&options,
+	)?;
+
+	assert!(!project.has("done.txt"));
+	assert_eq!(
+		git::changes(&project.dir)?,
+		BTreeMap::from([
+			(PathBuf::from("MyProject.txt"), Change::Added),
+			(PathBuf::from("README.md"), Change::Modified),
+			(PathBuf::from("{{NAME}}.txt"), Change::Deleted),
+		])
+	);
+
+	Ok(())
+}
+
+#[test]
+fn generate_moves_project_into_place() -> Result<()> {
+	let (_template_dir, recipe) = template_recipe(
+		&[("README.md", "# {{NAME}}")],
+		r#"commands = ["pwd -P > cwd.txt"]"#,
+	)?;
+	let project = Project::new()?;
+	fs::create_dir(&project.dir)?;
+
+	init::generate(
+		&recipe,
+		&project.dir,
+		"MyProject",
+		&Options::default(),
+		Conflict::default(),
+		false,
+	)?;
+
+	assert_eq!(
+		project.read("cwd.txt")?.trim_end(),
+		project.dir.canonicalize()?.to_string_lossy()
+	);
+	assert_eq!(fs::read_dir(project.root.path())?.count(), 1);
+
+	Ok(())
+}
+
+#[test]
+fn generate_cleans_up_on_failure() -> Result<()> {
+	let (_template_dir, recipe) =
+		template_recipe(&[("README.md", "# {{NAME}}")], r#"commands = ["exit 3"]"#)?;
+	let project = Project::new()?;
+	let options = Options::default();
+
+	let err = init::generate(
+		&recipe,
+		&project.dir,
+		"MyProject",
+		&options,
+		Conflict::default(),
+		false,
+	)
+	.expect_err("the command should fail the generation");
+	assert!(format!("{err:#}").contains("exit code 3"), "{err:#}");
+	assert_eq!(fs::read_dir(project.root.path())?.count(), 0);
+
+	init::generate(
+		&recipe,
+		&project.dir,
+		"MyProject",
+		&options,
+		Conflict::default(),
+		true,
+	)
+	.expect_err("the command should fail the generation");
+	assert!(project.has("README.md"));
+	assert_eq!(fs::read_dir(project.root.path())?.count(), 1);
+
+	Ok(())
+}
+
+#[test]
+fn generate_resolves_conflicts_in_existing_directories() -> Result<()> {
+	let (_template_dir, recipe) = template_recipe(
+		&[("README.md", "# {{NAME}}\nGenerated\n")],
+		r#"commands = ["echo done > done.txt"]"#,
+	)?;
+
+	for (conflict, expected) in [
+		(Conflict::Skip, "# Existing\n"),
+		(Conflict::Overwrite, "# MyProject\nGenerated\n"),
+		(
+			Conflict::Merge,
+			"<<<<<<< existing\n# Existing\n=======\n# MyProject\nGenerated\n>>>>>>> template\n",
+		),
+	] {
+		let project = Project::new()?;
+		fs::create_dir_all(project.dir.join(".git"))?;
+		fs::write(project.dir.join(".git/HEAD"), "ref: refs/heads/main")?;
+		fs::write(project.dir.join("README.md"), "# Existing\n")?;
+
+		init::generate(
+			&recipe,
+			&project.dir,
+			"MyProject",
+			&Options::default(),
+			conflict,
+			false,
+		)?;
+
+		assert_eq!(project.read("README.md")?, expected);
+		assert_eq!(project.read(".git/HEAD")?, "ref: refs/heads/main");
+		assert!(project.has("done.txt"));
+		assert_eq!(fs::read_dir(project.root.path())?.count(), 1);
+	}
+
+	Ok(())
+}
+
+#[test]
+fn add_applies_addon_to_existing_project() -> Result<()> {
+	let (_template_dir, mut recipe) = template_recipe(
+		&[
+			(".github/workflows/ci.yml", "name: {{NAME}} CI"),
+			("README.md", "# Template\n"),
+		],
+		r#"commands = ["echo done > done.txt"]"#,
+	)?;
+	recipe.kind = Kind::Addon;
+	let project = Project::new()?;
+	fs::create_dir_all(project.dir.join(".git"))?;
+	fs::write(project.dir.join(".git/HEAD"), "ref: refs/heads/main")?;
+	fs::write(project.dir.join("README.md"), "# My Project\n")?;
+
+	add::apply(
+		&recipe,
+		&project.dir,
+		"my-crate",
+		&Options::default(),
+		Conflict::Skip,
+	)?;
+
+	assert_eq!(
+		project.read(".github/workflows/ci.yml")?,
+		"name: my-crate CI"
+	);
+	assert_eq!(project.read("README.md")?, "# My Project\n");
+	assert_eq!(project.read(".git/HEAD")?, "ref: refs/heads/main");
+	assert!(project.has("done.txt"));
+	assert_eq!(fs::read_dir(project.root.path())?.count(), 1);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_applies_structured_commands() -> Result<()> {
+	let project = generate(
+		&[("README.md", "# {{NAME}}"), ("web/package.json", "{}")],
+		r#"
+		hooks = { remove_git = false }
+		replacements = { USE_WEB = "yes" }
+		commands = [
+			{ run = "echo {{NAME}} > generated.txt", stage = "pre_render" },
+			{ run = "echo $GREETING > greeting.txt", cwd = "web", env = { GREETING = "hi" }, when = "USE_WEB" },
+			{ run = "echo skipped > skipped.txt", when = "USE_DOCKER" },
+			{ run = "echo windows > windows.txt", os = ["windows"] },
+			{ run = "exit 1", allow_failure = true },
+			{ run = "(sleep 0.5 && echo late > late.txt) & sleep 5", timeout = "100ms", allow_failure = true },
+			"echo done > done.txt",
+		]
+		"#,
+	)?;
+
+	assert_eq!(project.read("generated.txt")?, "MyProject\n");
+	assert!(project.has(".git"));
+	assert_eq!(project.read("web/greeting.txt")?, "hi\n");
+	assert!(!project.has("skipped.txt"));
+	assert!(!project.has("windows.txt"));
+	assert!(project.has("done.txt"));
+
+	// Everything the timed out command started was killed along with it.
+	thread::sleep(Duration::from_secs(1));
+	assert!(!project.has("late.txt"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_renders_variables_into_commands() -> Result<()> {
+	let (template_dir, recipe) = template_recipe(
+		&[("README.md", "# {{NAME}}")],
+		r#"
+		replacements = { APP_ID = "com.example.app", GREETING = "it's $(touch pwned)" }
+		commands = [
+			"echo {{GREETING}} > greeting.txt",
+			"echo '{{.Id}} {{ .Config.Image | json }}' > format.txt",
+			"echo {{NAME | snake_case}} $NEW_VAR_APP_ID > vars.txt",
+			"echo $NEW_PROJECT_NAME $NEW_RECIPE $NEW_TEMPLATE_SHA > project.txt",
+			{ run = "echo $ID > id.txt", env = { ID = "{{APP_ID}}" } },
+			"echo \\{{literal}} > escaped.txt",
+		]
+		"#,
+	)?;
+	let sha = Repository::open(template_dir.path())?
+		.head()?
+		.peel_to_commit()?
+		.id()
+		.to_string();
+	let project = Project::new()?;
+
+	recipe.run(&project.dir, "MyProject", &Options::default())?;
+
+	assert_eq!(project.read("greeting.txt")?, "it's $(touch pwned)\n");
+	assert!(!project.has("pwned"));
+	assert_eq!(
+		project.read("format.txt")?,
+		"{{.Id}} {{ .Config.Image | json }}\n"
+	);
+	assert_eq!(project.read("vars.txt")?, "my_project com.example.app\n");
+	assert_eq!(
+		project.read("project.txt")?,
+		format!("MyProject local {sha}\n")
+	);
+	assert_eq!(project.read("id.txt")?, "com.example.app\n");
+	assert_eq!(project.read("escaped.txt")?, "{{literal}}\n");
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_runs_on_error_stage_when_a_stage_fails() -> Result<()> {
+	let project = Project::new()?;
+	project
+		.generate(
+			&[("README.md", "# {{NAME}}")],
+			r#"
+			commands = [
+				"exit 1",
+				{ run = "echo finalized > finalized.txt", stage = "finalize" },
+				{ run = "echo failed > failed.txt", stage = "on_error" },
+			]
+			"#,
+			&Options::default(),
+		)
+		.expect_err("the command should fail the generation");
+
+	assert!(project.has("failed.txt"));
+	assert!(!project.has("finalized.txt"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_runs_template_scripts() -> Result<()> {
+	let project = Project::new()?;
+	let options = Options {
+		trust_template: Some(true),
+		..Options::default()
+	};
+	project.generate(
+		&[
+			(
+				".new/recipe.toml",
+				"[recipe.scripts]\npre_render = \"sh\"\npost_render = \"sh -e\"\n",
+			),
+			(".new/hooks/pre_render", "cat > answers.json\n"),
+			(
+				".new/hooks/post_render.sh",
+				"echo \"$NEW_VAR_NAME {{NOT_A_PLACEHOLDER}}\" > post.txt\n",
+			),
+			("README.md", "# {{NAME}}"),
+		],
+		r#"replacements = { APP_ID = "com.example.app" }"#,
+		&options,
+	)?;
+
+	assert_eq!(
+		project.read("answers.json")?,
+		r#"{"APP_ID":"com.example.app","NAME":"MyProject"}"#
+	);
+	assert_eq!(
+		project.read("post.txt")?,
+		"MyProject {{NOT_A_PLACEHOLDER}}\n"
+	);
+	assert!(!project.has(".new"));
+
+	Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn recipe_run_runs_plugins() -> Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+
+	let plugin_dir = temp_dir("new-cli-plugin")?;
+	let plugin = plugin_dir.path().join("new-hook-catalog");
+	fs::write(
+		&plugin,
+		r#"#!/bin/sh
+cat > request.json
+echo '{"variables": {"SERVICE_ID": "svc-42"}, "files": [{"action": "write", "path": "catalog/service.txt", "contents": "registered"}, {"action": "remove", "path": "README.md"}]}'
+"#,
+	)?;
+	fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755))?;
+	let audit = plugin_dir.path().join("new-hook-audit");
+	fs::write(
+		&audit,
+		format!(
+			"#!/bin/sh\npwd -P > {:?}\n",
+			plugin_dir.path().join("audit.txt")
+		),
+	)?;
+	fs::set_permissions(&audit, fs::Permissions::from_mode(0o755))?;
+
+	let config = format!(
+		r#"
+		commands = ["echo {{{{SERVICE_ID}}}} > service-id.txt"]
+		plugins.catalog = {{ path = {:?}, stages = ["post_render"] }}
+		plugins.audit = {{ path = {:?}, stages = ["pre_clone"] }}
+		"#,
+		plugin.display().to_string(),
+		audit.display().to_string()
+	);
+	let project = generate(&[("README.md", "# {{NAME}}")], &config)?;
+
+	let request = project.read("request.json")?;
+	assert!(request.contains(r#""stage":"post_render""#));
+	assert!(request.contains(r#""project_name":"MyProject""#));
+	assert_eq!(project.read("catalog/service.txt")?, "registered");
+	assert!(!project.has("README.md"));
+	assert_eq!(project.read("service-id.txt")?, "svc-42\n");
+	// Before cloning, plugins run where `new` was started.
+	assert_eq!(
+		fs::read_to_string(plugin_dir.path().join("audit.txt"))?.trim_end(),
+		env::current_dir()?.canonicalize()?.to_string_lossy()
+	);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_runs_embedded_scripts() -> Result<()> {
+	let project = Project::new()?;
+	// Modules can't be imported from anywhere, even with an absolute path.
+	fs::write(
+		project.root.path().join("outside.rhai"),
+		"export const VALUE = 1;",
+	)?;
+	let import = format!(
+		"import {:?} as outside; write_file(\"imported.txt\", \"yes\");",
+		project.root.path().join("outside").display().to_string()
+	);
+	let config = r#"
+		commands = [
+			{ script = 'let name = variable("NAME"); write_file("docs/name.txt", name); set_variable("GREETING", "hello " + name); rename("README.md", "docs/README.md");' },
+			"echo {{GREETING}} > greeting.txt",
+			{ script_file = "setup.rhai" },
+			{ script = 'write_file("../escape.txt", "no")', allow_failure = true },
+			{ script = 'loop {}', timeout = "100ms", allow_failure = true },
+			{ script = 'delete("")', allow_failure = true },
+			{ script = 'delete("./")', allow_failure = true },
+			"ln -s .. up",
+			{ script = 'write_file("up/escape.txt", "no")', allow_failure = true },
+			{ script = IMPORT, allow_failure = true },
+		]
+		"#
+	.replace("IMPORT", &format!("{import:?}"));
+
+	project.generate(
+		&[
+			("README.md", "# {{NAME}}"),
+			(
+				"setup.rhai",
+				"delete(\"setup.rhai\");\nif exists(\"docs/name.txt\") { write_file(\"ok.txt\", STAGE); }\n",
+			),
+		],
+		&config,
+		&Options::default(),
+	)?;
+
+	assert_eq!(project.read("docs/name.txt")?, "MyProject");
+	assert_eq!(project.read("docs/README.md")?, "# MyProject");
+	assert_eq!(project.read("greeting.txt")?, "hello MyProject\n");
+	assert_eq!(project.read("ok.txt")?, "commands");
+	assert!(!project.has("setup.rhai"));
+	assert!(!project.root.path().join("escape.txt").exists());
+	assert!(!project.has("imported.txt"));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_shares_variables_and_records_provenance() -> Result<()> {
+	if !in_isolated_home(
+		"tests::recipe_run_shares_variables_and_records_provenance",
+		"[user]\n\tname = Ada Lovelace\n\temail = ada@example.com\n",
+	)? {
+		return Ok(());
+	}
+
+	let project = generate(
+		&[("README.md", "# {{NAME}} by {{AUTHOR}}")],
+		r#"
+		provenance = ".new/provenance.toml"
+		commands = [
+			{ script = 'set_variable("SERVICE", "api")' },
+			"echo $NEW_VAR_AUTHOR {{SERVICE}} > author.txt",
+		]
+		"#,
+	)?;
+
+	assert_eq!(project.read("README.md")?, "# MyProject by Ada Lovelace");
+	assert_eq!(project.read("author.txt")?, "Ada Lovelace api\n");
+
+	let provenance = project
+		.read(".new/provenance.toml")?
+		.parse::<toml::Table>()?;
+	assert_eq!(provenance["recipe"].as_str(), Some("local"));
+	assert_eq!(provenance["variables"]["SERVICE"].as_str(), Some("api"));
+
+	let events = provenance["events"]
+		.as_array()
+		.map(|events| {
+			events
+				.iter()
+				.map(|event| {
+					format!(
+						"{}/{}: {}",
+						event["stage"].as_str().unwrap_or_default(),
+						event["hook"].as_str().unwrap_or_default(),
+						event["message"].as_str().unwrap_or_default()
+					)
+				})
+				.collect::<Vec<_>>()
+		})
+		.unwrap_or_default();
+	assert!(events.contains(&"commands/run_commands: Set SERVICE".to_string()));
+	assert!(events.contains(&"commands/run_commands: Ran inline script".to_string()));
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_quiet_mode_logs_command_output() -> Result<()> {
+	if !in_isolated_home("tests::recipe_run_quiet_mode_logs_command_output", "")? {
+		return Ok(());
+	}
+
+	let project = Project::new()?;
+	let options = Options {
+		quiet: true,
+		..Options::default()
+	};
+	project.generate(
+		&[("README.md", "# {{NAME}}")],
+		r#"
+		commands = [
+			{ run = "echo noisy; echo broken >&2; exit 3", allow_failure = true },
+			"echo done > done.txt",
+		]
+		"#,
+		&options,
+	)?;
+
+	assert_eq!(project.read("done.txt")?, "done\n");
+	let logs_dir = dirs::state_dir()
+		.or_else(dirs::data_local_dir)
+		.context("No state directory")?
+		.join("new")
+		.join("logs");
+	let logs = fs::read_dir(logs_dir)?.collect::<Result<Vec<_>, _>>()?;
+	assert_eq!(logs.len(), 1);
+	assert!(fs::read_to_string(logs[0].path())?.contains("noisy\nbroken\n"));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn recipe_run_runs_command_groups_in_parallel() -> Result<()> {
+	if !in_isolated_home("tests::recipe_run_runs_command_groups_in_parallel", "")? {
+		return Ok(());
+	}
+
+	let project = generate(
+		&[("README.md", "# {{NAME}}"), ("web/.keep", "")],
+		r#"
+		commands = [
+			{ run = "while [ ! -f web/started ]; do sleep 0.05; done; echo api > api.txt", cwd = ".", group = "setup", timeout = "5s" },
+			{ run = "touch started; echo web > web.txt", cwd = "web", group = "setup" },
+			"cat api.txt web/web.txt > both.txt",
+		]
+		"#,
+	)?;
+
+	// The first command only finishes once the second one started, so they ran side by side.
+	assert_eq!(project.read("both.txt")?, "api\nweb\n");
+
+	let err = Project::new()?
+		.generate(
+			&[("README.md", "# {{NAME}}")],
+			r#"
+			commands = [
+				{ run = "exit 2", group = "checks" },
+				{ run = "exit 3", group = "checks" },
+				{ run = "echo fine > fine.txt", group = "checks" },
+			]
+			"#,
+			&Options::default(),
+		)
+		.expect_err("the group should fail");
+	let message = format!("{err:#}");
+
+	assert!(
+		message.contains("2 commands in group checks failed"),
+		"{message}"
+	);
+	assert!(
+		message.contains("`exit 2` failed with exit code 2"),
+		"{message}"
+	);
+	assert!(
+		message.contains("`exit 3` failed with exit code 3"),
+		"{message}"
+	);
+
+	Ok(())
+}
+
+#[test]
+fn recipe_run_checks_required_tools_before_cloning() -> Result<()> {
+	let project = Project::new()?;
+	let err = project
+		.generate(
+			&[("README.md", "# {{NAME}}")],
+			r#"
+			requires = [
+				"git >= 1",
+				"git >= 999",
+				{ tool = "new-cli-missing-tool", hint = "Install it from the docs" },
+			]
+			"#,
+			&Options::default(),
+		)
+		.expect_err("the missing tools should fail the generation");
+	let message = format!("{err:#}");
+
+	assert!(
+		message.contains("2 required tools are missing or too old"),
+		"{message}"
+	);
+	assert!(message.contains("git >=999 is required, but"), "{message}");
+	assert!(
+		message.contains("new-cli-missing-tool is not installed. Install it from the docs"),
+		"{message}"
+	);
+	assert!(!project.dir.exists());
+
+	Ok(())
+}
+
+#[test]
+fn generate_checks_tools_required_by_the_manifest() -> Result<()> {
+	let (_template_dir, recipe) = template_recipe(
+		&[
+			("README.md", "# {{NAME}}"),
+			(
+				"new.toml",
+				"[recipe]\nrequires = [\"new-cli-missing-tool\"]\n",
+			),
+		],
+		"",
+	)?;
+	let project = Project::new()?;
+
+	let err = init::generate(
+		&recipe,
+		&project.dir,
+		"MyProject",
+		&Options::default(),
+		Conflict::default(),
+		false,
+	)
+	.expect_err("the missing tool should fail the generation");
+
+	assert!(
+		format!("{err:#}").contains("new-cli-missing-tool is not installed"),
+		"{err:#}"
+	);
+	assert_eq!(fs::read_dir(project.root.path())?.count(), 0);
+
+	// Checking a tool runs it, so an untrusted template can't require a program of its own.
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+
+		let tools_dir = temp_dir("new-cli-tools")?;
+		let (evil, marker) = (tools_dir.path().join("evil"), tools_dir.path().join("ran"));
+		fs::write(
+			&evil,
+			format!("#!/bin/sh\ntouch '{}'\necho 1.0.0\n", marker.display()),
+		)?;
+		fs::set_permissions(&evil, fs::Permissions::from_mode(0o755))?;
+
+		let manifest = format!("[recipe]\nrequires = [\"{} >= 1\"]\n", evil.display());
+		let (_template_dir, recipe) =
+			template_recipe(&[("README.md", "# {{NAME}}"), ("new.toml", &manifest)], "")?;
+		let options = Options {
+			trust_template: Some(false),
+			..Options::default()
+		};
+
+		let err = init::generate(
+			&recipe,
+			&project.dir,
+			"MyProject",
+			&options,
+			Conflict::default(),
+			false,
+		)
+		.expect_err("a path in requires should fail the generation");
+
+		assert!(format!("{err:#}").contains("not a path"), "{err:#}");
+		assert!(!marker.exists());
+	}
+
+	Ok(())
+}